@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pdf::reader::PDFReader;
+use pdf::structure::AnyPDFData;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = AnyPDFData::consume(&mut PDFReader::from(data));
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pdf::parser::{parse, parse_with, ParseOptions};
+use pdf::reader::PDFReader;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse(&mut PDFReader::from(data));
+    let _ = parse_with(&mut PDFReader::from(data), ParseOptions::strict());
+});
@@ -1 +1 @@
-export RUST_BACKTRACE=1 && cargo +nightly run && cargo +nightly test 2>/dev/null
+export RUST_BACKTRACE=1 && cargo run -- src/test.pdf && cargo test 2>/dev/null
@@ -0,0 +1,299 @@
+use crate::error::PdfError;
+use crate::filter::{self, DecodeOptions, Filter};
+use crate::parser::{
+    find_compressed, load_object, object_stream_contents, parse_with, ParseOptions,
+};
+use crate::reader::{PDFReader, SeekReader, StreamReader};
+use crate::structure::{AnyPDFData, Dictionary, Object, Revision, Stream, XrefEntry, PDF};
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::Path;
+
+/// How many references `resolve_deep` follows inside each other before
+/// giving up, so that long chains of objects cannot overflow the stack.
+const MAX_REFERENCE_DEPTH: usize = 256;
+
+/// How many values `resolve_deep` may produce. Objects referenced from many
+/// places are copied into each, so a short chain of objects that each refer
+/// to the next twice would otherwise grow exponentially.
+const MAX_RESOLVED_VALUES: usize = 1 << 20;
+
+/// The state of one `resolve_deep` call: the references being resolved, and
+/// how many values have been produced so far.
+#[derive(Default)]
+struct Resolution {
+    stack: Vec<(i64, i64)>,
+    values: usize,
+}
+
+/// An opened PDF file. Only the index and trailer are read up front; objects
+/// are loaded from the underlying reader when they are first asked for, and
+/// kept afterwards, as are the contents of the object streams they come from.
+pub struct Document<R: StreamReader> {
+    reader: R,
+    pdf: PDF,
+    cache: BTreeMap<i64, Object>,
+    object_streams: BTreeMap<i64, Vec<Object>>,
+}
+
+impl Document<PDFReader<'static>> {
+    /// Reads the whole file at `path` into memory and opens it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PdfError> {
+        Self::load(PDFReader::open(path)?, ParseOptions::default())
+    }
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, PdfError> {
+        Self::load(PDFReader::from(bytes), ParseOptions::default())
+    }
+}
+
+impl<'a> Document<PDFReader<'a>> {
+    pub fn from_slice(bytes: &'a [u8]) -> Result<Self, PdfError> {
+        Self::load(PDFReader::from(bytes), ParseOptions::default())
+    }
+}
+
+impl<S: io::Read + io::Seek> Document<SeekReader<S>> {
+    /// Opens a document from any seekable source, such as a `File`, without
+    /// reading all of it into memory.
+    pub fn from_reader(source: S) -> Result<Self, PdfError> {
+        let mut document = Self::load(SeekReader::new(source)?, ParseOptions::default())?;
+        match document.reader.take_error() {
+            Some(e) => Err(e.into()),
+            None => Ok(document),
+        }
+    }
+}
+
+impl<R: StreamReader> Document<R> {
+    /// Opens a document from any reader. `options.load_objects` is ignored:
+    /// objects are always loaded lazily.
+    pub fn load(mut reader: R, options: ParseOptions) -> Result<Self, PdfError> {
+        let options = ParseOptions {
+            load_objects: false,
+            ..options
+        };
+        let pdf = parse_with(&mut reader, options)?;
+        Ok(Document {
+            reader,
+            pdf,
+            cache: BTreeMap::new(),
+            object_streams: BTreeMap::new(),
+        })
+    }
+    /// The `%PDF-x.y` header line, or an empty string if it is missing.
+    pub fn version(&self) -> &str {
+        &self.pdf.version
+    }
+    pub fn trailer(&self) -> &Dictionary {
+        &self.pdf.trailer
+    }
+    pub fn xref(&self) -> &BTreeMap<i64, XrefEntry> {
+        &self.pdf.xref
+    }
+    pub fn revisions(&self) -> &[Revision] {
+        &self.pdf.revisions
+    }
+    /// Why the index had to be rebuilt by scanning the file, if it was.
+    pub fn recovered(&self) -> Option<&str> {
+        self.pdf.recovered.as_deref()
+    }
+    /// Whether the trailer has an /Encrypt dictionary. The strings and
+    /// streams of encrypted documents are kept as they are stored, still
+    /// encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.pdf.trailer.contains_key("Encrypt")
+    }
+    /// Fetches the object `number`. Free and unknown objects give `None`.
+    /// Objects stored in object streams go through `decode_stream`, so in
+    /// encrypted documents they give an encryption error.
+    pub fn get_object(&mut self, number: i64) -> Result<Option<&Object>, PdfError> {
+        if !self.cache.contains_key(&number) {
+            let object = match self.pdf.xref.get(&number) {
+                Some(&XrefEntry::Compressed { stream, index }) => self
+                    .load_compressed(stream, number, index)
+                    .map_err(|e| e.in_object(number, 0))?,
+                _ => load_object(&mut self.reader, &self.pdf.xref, number)?,
+            };
+            let Some(object) = object else {
+                return Ok(None);
+            };
+            self.cache.insert(number, object);
+        }
+        Ok(self.cache.get(&number))
+    }
+    /// Loads the object `number` from the object stream `stream`, where the
+    /// index lists it at `index`. The stream is decoded and unpacked once.
+    fn load_compressed(
+        &mut self,
+        stream: i64,
+        number: i64,
+        index: i64,
+    ) -> Result<Option<Object>, PdfError> {
+        if !self.object_streams.contains_key(&stream) {
+            let contained = self.unpack_object_stream(stream)?;
+            self.object_streams.insert(stream, contained);
+        }
+        Ok(find_compressed(&self.object_streams[&stream], number, index).cloned())
+    }
+    fn unpack_object_stream(&mut self, stream: i64) -> Result<Vec<Object>, PdfError> {
+        if !matches!(self.pdf.xref.get(&stream), Some(XrefEntry::InUse { .. })) {
+            return Err(PdfError::resolution(format!(
+                "Object stream {} is not in use",
+                stream
+            )));
+        }
+        let Some(object) = load_object(&mut self.reader, &self.pdf.xref, stream)? else {
+            return Ok(vec![]);
+        };
+        let AnyPDFData::Stream(data) = &object.data else {
+            return Err(PdfError::syntax("Object stream must be a stream")
+                .in_object(object.number, object.gen));
+        };
+        self.decode_stream(data)
+            .and_then(|decoded| object_stream_contents(&data.dict, decoded, false))
+            .map_err(|e| e.in_object(object.number, object.gen))
+    }
+    /// Fetches the object an indirect reference such as `3 0 R` points to.
+    /// The reference must carry the generation the index lists for that
+    /// object; otherwise it points to an object which has since been freed or
+    /// replaced, and `None` is returned.
+    pub fn get_reference(&mut self, number: i64, gen: i64) -> Result<Option<&Object>, PdfError> {
+        let current = match self.pdf.xref.get(&number) {
+            Some(XrefEntry::InUse { gen, .. }) => *gen,
+            // objects in object streams always have generation 0
+            Some(XrefEntry::Compressed { .. }) => 0,
+            Some(XrefEntry::Free { .. }) | None => return Ok(None),
+        };
+        if current != gen {
+            return Ok(None);
+        }
+        Ok(self.get_object(number)?.filter(|object| object.gen == gen))
+    }
+    /// Follows `data` if it is a reference, and any reference the object it
+    /// points to is itself, returning the direct value. References to free,
+    /// missing or stale objects resolve to null. Anything else is returned
+    /// as it is.
+    pub fn resolve(&mut self, data: &AnyPDFData) -> Result<AnyPDFData, PdfError> {
+        let mut seen = HashSet::new();
+        let mut data = data.clone();
+        while let AnyPDFData::ObjRef(number, gen) = data {
+            if !seen.insert((number, gen)) {
+                return Err(PdfError::resolution("Reference cycle").in_object(number, gen));
+            }
+            data = match self.get_reference(number, gen)? {
+                Some(object) => object.data.clone(),
+                None => AnyPDFData::Null,
+            };
+        }
+        Ok(data)
+    }
+    /// Replaces every reference inside `data`, however deeply nested, with
+    /// the object it points to. A reference back to an object that is being
+    /// resolved, such as a page's /Parent, is left as a reference. Values
+    /// which would resolve to more than `MAX_RESOLVED_VALUES` values give a
+    /// resolution error.
+    pub fn resolve_deep(&mut self, data: &AnyPDFData) -> Result<AnyPDFData, PdfError> {
+        self.resolve_within(data, &mut Resolution::default())
+    }
+    fn resolve_within(
+        &mut self,
+        data: &AnyPDFData,
+        state: &mut Resolution,
+    ) -> Result<AnyPDFData, PdfError> {
+        state.values += 1;
+        if state.values > MAX_RESOLVED_VALUES {
+            return Err(PdfError::resolution("Resolved object is too large"));
+        }
+        match data {
+            AnyPDFData::ObjRef(number, gen) => {
+                if state.stack.contains(&(*number, *gen)) {
+                    return Ok(data.clone());
+                }
+                if state.stack.len() >= MAX_REFERENCE_DEPTH {
+                    return Err(PdfError::resolution("References are nested too deeply")
+                        .in_object(*number, *gen));
+                }
+                let target = match self.get_reference(*number, *gen)? {
+                    Some(object) => object.data.clone(),
+                    None => return Ok(AnyPDFData::Null),
+                };
+                state.stack.push((*number, *gen));
+                let resolved = self.resolve_within(&target, state);
+                state.stack.pop();
+                resolved
+            }
+            AnyPDFData::Array(items) => Ok(AnyPDFData::Array(
+                items
+                    .iter()
+                    .map(|item| self.resolve_within(item, state))
+                    .collect::<Result<_, _>>()?,
+            )),
+            AnyPDFData::Dictionary(dict) => {
+                Ok(AnyPDFData::Dictionary(self.resolve_dict(dict, state)?))
+            }
+            AnyPDFData::Stream(stream) => Ok(AnyPDFData::Stream(Stream {
+                dict: self.resolve_dict(&stream.dict, state)?,
+                data: stream.data.clone(),
+            })),
+            _ => Ok(data.clone()),
+        }
+    }
+    fn resolve_dict(
+        &mut self,
+        dict: &Dictionary,
+        state: &mut Resolution,
+    ) -> Result<Dictionary, PdfError> {
+        dict.iter()
+            .map(|(key, value)| Ok((key.clone(), self.resolve_within(value, state)?)))
+            .collect()
+    }
+    /// Decodes the data of `stream`, first resolving any references among
+    /// its filters and their parameters. Streams of encrypted documents give
+    /// an encryption error, as they cannot be decrypted yet, unless they go
+    /// through a crypt filter or are cross-reference streams, which are never
+    /// encrypted.
+    pub fn decode_stream(&mut self, stream: &Stream) -> Result<Vec<u8>, PdfError> {
+        self.decode_stream_with(stream, DecodeOptions::default())
+    }
+    pub fn decode_stream_with(
+        &mut self,
+        stream: &Stream,
+        options: DecodeOptions,
+    ) -> Result<Vec<u8>, PdfError> {
+        let mut dict = stream.dict.clone();
+        for key in ["Filter", "DecodeParms"] {
+            if let Some(value) = stream.dict.get(key) {
+                dict.insert(key, self.resolve_deep(value)?);
+            }
+        }
+        if self.is_encrypted()
+            && !dict.get_name("Type").is_ok_and(|x| x == "XRef")
+            && filter::filters(&dict)?.first().map(|x| x.0) != Some(Filter::Crypt)
+        {
+            return Err(PdfError::encryption(
+                "Streams of encrypted documents cannot be decrypted",
+            ));
+        }
+        filter::decode_with(&dict, &stream.data, options)
+    }
+    /// The document catalog, which the trailer's /Root points to.
+    pub fn catalog(&mut self) -> Result<&Dictionary, PdfError> {
+        let (number, gen) = self.pdf.trailer.get_ref("Root")?;
+        match self.get_reference(number, gen)? {
+            Some(Object {
+                data: AnyPDFData::Dictionary(catalog),
+                ..
+            }) => Ok(catalog),
+            Some(object) => Err(PdfError::syntax(format!(
+                "Expected the catalog to be a dictionary, found {}",
+                object.data.type_name()
+            ))
+            .in_object(number, gen)),
+            None => Err(PdfError::resolution("The catalog is missing").in_object(number, gen)),
+        }
+    }
+    /// Gives back the underlying reader.
+    pub fn into_reader(self) -> R {
+        self.reader
+    }
+}
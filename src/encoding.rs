@@ -0,0 +1,55 @@
+/// The characters PDFDocEncoding assigns to bytes 0x18-0x1F and 0x80-0xA0,
+/// where it differs from Latin-1. Undefined codes map to U+FFFD.
+const PDFDOC_18_1F: [char; 8] = ['˘', 'ˇ', 'ˆ', '˙', '˝', '˛', '˚', '˜'];
+const PDFDOC_80_A0: [char; 33] = [
+    '•', '†', '‡', '…', '—', '–', 'ƒ', '⁄', '‹', '›', '−', '‰', '„', '“', '”', '‘', '’', '‚', '™',
+    'ﬁ', 'ﬂ', 'Ł', 'Œ', 'Š', 'Ÿ', 'Ž', 'ı', 'ł', 'œ', 'š', 'ž', '\u{FFFD}', '€',
+];
+
+/// Decodes bytes in PDFDocEncoding, the default encoding of text strings.
+pub fn decode_pdfdoc(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            0x18..=0x1F => PDFDOC_18_1F[(byte - 0x18) as usize],
+            0x80..=0xA0 => PDFDOC_80_A0[(byte - 0x80) as usize],
+            0x7F | 0xAD => '\u{FFFD}',
+            _ => char::from(byte),
+        })
+        .collect()
+}
+
+/// Decodes UTF-16BE bytes, without a byte order mark. Language escapes
+/// (`ESC` language code `ESC`) are dropped, and unpaired surrogates or an odd
+/// final byte become U+FFFD.
+pub fn decode_utf16be(bytes: &[u8]) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+    let mut text = String::new();
+    let mut in_escape = false;
+    for decoded in char::decode_utf16(units) {
+        match decoded {
+            Ok('\u{1B}') => in_escape = !in_escape,
+            _ if in_escape => {}
+            Ok(c) => text.push(c),
+            Err(_) => text.push('\u{FFFD}'),
+        }
+    }
+    if bytes.len() % 2 == 1 {
+        text.push('\u{FFFD}');
+    }
+    text
+}
+
+/// Decodes a text string: UTF-16BE or UTF-8 if it starts with the matching
+/// byte order mark, PDFDocEncoding otherwise.
+pub fn decode_text(bytes: &[u8]) -> String {
+    if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        decode_utf16be(rest)
+    } else if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        String::from_utf8_lossy(rest).into_owned()
+    } else {
+        decode_pdfdoc(bytes)
+    }
+}
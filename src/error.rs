@@ -0,0 +1,158 @@
+use crate::reader::StreamReader;
+use std::fmt;
+use std::io;
+
+/// How many bytes of input an error quotes.
+const EXCERPT_LEN: usize = 24;
+
+/// Where and why parsing failed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorDetail {
+    pub message: String,
+    /// The byte offset of the failure. Inside an object stream, this is an
+    /// offset into the decoded stream data.
+    pub offset: Option<usize>,
+    /// The number and generation of the object being parsed.
+    pub object: Option<(i64, i64)>,
+    /// The input starting at `offset`, cut short.
+    pub excerpt: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum PdfError {
+    /// Reading the underlying file failed.
+    Io(io::Error),
+    /// Bytes that do not form a token, like a stray `)`.
+    Lex(ErrorDetail),
+    /// Tokens that do not form the expected object.
+    Syntax(ErrorDetail),
+    /// The input ended in the middle of a token or an object.
+    Truncated(ErrorDetail),
+    /// Broken `startxref`, xref tables, xref streams or trailers.
+    Xref(ErrorDetail),
+    /// Stream data that could not be decoded.
+    Filter(ErrorDetail),
+    /// Data which is encrypted and cannot be read.
+    Encryption(ErrorDetail),
+    /// A reference to an object that cannot be found or loaded.
+    Resolution(ErrorDetail),
+}
+
+impl PdfError {
+    pub fn lex(message: impl Into<String>) -> Self {
+        PdfError::Lex(ErrorDetail::new(message))
+    }
+    pub fn syntax(message: impl Into<String>) -> Self {
+        PdfError::Syntax(ErrorDetail::new(message))
+    }
+    pub fn truncated(message: impl Into<String>) -> Self {
+        PdfError::Truncated(ErrorDetail::new(message))
+    }
+    pub fn xref(message: impl Into<String>) -> Self {
+        PdfError::Xref(ErrorDetail::new(message))
+    }
+    pub fn filter(message: impl Into<String>) -> Self {
+        PdfError::Filter(ErrorDetail::new(message))
+    }
+    pub fn encryption(message: impl Into<String>) -> Self {
+        PdfError::Encryption(ErrorDetail::new(message))
+    }
+    pub fn resolution(message: impl Into<String>) -> Self {
+        PdfError::Resolution(ErrorDetail::new(message))
+    }
+    pub fn detail(&self) -> Option<&ErrorDetail> {
+        match self {
+            PdfError::Io(_) => None,
+            PdfError::Lex(detail)
+            | PdfError::Syntax(detail)
+            | PdfError::Truncated(detail)
+            | PdfError::Xref(detail)
+            | PdfError::Filter(detail)
+            | PdfError::Encryption(detail)
+            | PdfError::Resolution(detail) => Some(detail),
+        }
+    }
+    fn detail_mut(&mut self) -> Option<&mut ErrorDetail> {
+        match self {
+            PdfError::Io(_) => None,
+            PdfError::Lex(detail)
+            | PdfError::Syntax(detail)
+            | PdfError::Truncated(detail)
+            | PdfError::Xref(detail)
+            | PdfError::Filter(detail)
+            | PdfError::Encryption(detail)
+            | PdfError::Resolution(detail) => Some(detail),
+        }
+    }
+    /// Records where the error happened, quoting the input there, unless an
+    /// earlier, more precise offset is already known.
+    pub fn at<R: StreamReader>(mut self, reader: &mut R, offset: usize) -> Self {
+        if let Some(detail) = self.detail_mut().filter(|x| x.offset.is_none()) {
+            detail.offset = Some(offset);
+            detail.excerpt = reader.slice(offset..offset + EXCERPT_LEN).to_vec();
+        }
+        self
+    }
+    /// Records the object being parsed, unless the error happened inside a
+    /// nested object which is already recorded.
+    pub fn in_object(mut self, number: i64, gen: i64) -> Self {
+        if let Some(detail) = self.detail_mut().filter(|x| x.object.is_none()) {
+            detail.object = Some((number, gen));
+        }
+        self
+    }
+}
+
+impl ErrorDetail {
+    pub fn new(message: impl Into<String>) -> Self {
+        ErrorDetail {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for PdfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self {
+            PdfError::Io(e) => return write!(f, "I/O error: {}", e),
+            PdfError::Lex(_) => "Lexing",
+            PdfError::Syntax(_) => "Syntax",
+            PdfError::Truncated(_) => "Truncation",
+            PdfError::Xref(_) => "Xref",
+            PdfError::Filter(_) => "Filter",
+            PdfError::Encryption(_) => "Encryption",
+            PdfError::Resolution(_) => "Resolution",
+        };
+        let Some(detail) = self.detail() else {
+            return Ok(());
+        };
+        write!(f, "{} error", kind)?;
+        if let Some((number, gen)) = detail.object {
+            write!(f, " in object {} {}", number, gen)?;
+        }
+        if let Some(offset) = detail.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        write!(f, ": {}", detail.message)?;
+        if !detail.excerpt.is_empty() {
+            write!(f, " near \"{}\"", detail.excerpt.escape_ascii())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PdfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PdfError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PdfError {
+    fn from(e: io::Error) -> Self {
+        PdfError::Io(e)
+    }
+}
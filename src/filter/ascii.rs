@@ -0,0 +1,87 @@
+//! ASCIIHexDecode and ASCII85Decode, which carry binary data as text.
+use crate::error::PdfError;
+use crate::lexer::is_whitespace;
+
+/// Decodes pairs of hex digits up to the `>` marking the end of the data.
+/// Whitespace is ignored, and a final odd digit is taken as followed by 0.
+pub fn decode_hex(data: &[u8]) -> Result<Vec<u8>, PdfError> {
+    let mut output = Vec::with_capacity(data.len() / 2);
+    let mut high: Option<u8> = None;
+    for &byte in data {
+        let digit = match byte {
+            b'0'..=b'9' => byte - b'0',
+            b'a'..=b'f' => byte - b'a' + 10,
+            b'A'..=b'F' => byte - b'A' + 10,
+            b'>' => break,
+            _ if is_whitespace(byte) => continue,
+            _ => {
+                return Err(PdfError::filter(format!(
+                    "Invalid character {:?} in ASCIIHexDecode data",
+                    byte as char
+                )))
+            }
+        };
+        match high.take() {
+            Some(high) => output.push(high << 4 | digit),
+            None => high = Some(digit),
+        }
+    }
+    if let Some(high) = high {
+        output.push(high << 4);
+    }
+    Ok(output)
+}
+
+/// Decodes groups of five base-85 digits into four bytes each, up to the
+/// `~>` marking the end of the data. `z` stands for four zero bytes, and a
+/// final group of n digits gives n - 1 bytes.
+pub fn decode_85(data: &[u8]) -> Result<Vec<u8>, PdfError> {
+    let data = data.strip_prefix(b"<~").unwrap_or(data);
+    let mut output = Vec::with_capacity(data.len() / 5 * 4);
+    let mut group = [0u8; 5];
+    let mut count = 0;
+    for &byte in data {
+        match byte {
+            b'!'..=b'u' => {
+                group[count] = byte - b'!';
+                count += 1;
+                if count == 5 {
+                    output.extend_from_slice(&base85_group(&group)?);
+                    count = 0;
+                }
+            }
+            b'z' if count == 0 => output.extend_from_slice(&[0; 4]),
+            b'~' => break,
+            _ if is_whitespace(byte) => {}
+            _ => {
+                return Err(PdfError::filter(format!(
+                    "Invalid character {:?} in ASCII85Decode data",
+                    byte as char
+                )))
+            }
+        }
+    }
+    match count {
+        0 => {}
+        1 => {
+            return Err(PdfError::filter(
+                "ASCII85Decode data ends with a lone digit",
+            ))
+        }
+        _ => {
+            // pad with the highest digit, so that rounding down is exact
+            group[count..].fill(b'u' - b'!');
+            output.extend_from_slice(&base85_group(&group)?[..count - 1]);
+        }
+    }
+    Ok(output)
+}
+
+fn base85_group(group: &[u8; 5]) -> Result<[u8; 4], PdfError> {
+    let value = group
+        .iter()
+        .fold(0u64, |value, &digit| value * 85 + digit as u64);
+    u32::try_from(value)
+        .map(u32::to_be_bytes)
+        .map_err(|_| PdfError::filter("ASCII85Decode group is out of range"))
+}
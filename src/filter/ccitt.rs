@@ -0,0 +1,324 @@
+//! CCITTFaxDecode: the Group 3 and Group 4 fax compression of ITU-T T.4 and
+//! T.6, decoding to a 1-bit bitmap.
+use super::{bool_parm, int_parm, BitReader};
+use crate::error::PdfError;
+use crate::structure::Dictionary;
+use std::sync::OnceLock;
+
+/// The longest run length code.
+const MAX_CODE_LEN: usize = 13;
+/// Two EOL codes in a row end Group 4 data.
+const END_OF_BLOCK: u32 = 0x001001;
+/// Wider than any fax. This only bounds the length of a row: as a row can be
+/// coded in a single bit, it is the limit on decoded data that bounds the
+/// output.
+const MAX_COLUMNS: usize = 1 << 16;
+
+/// The codes for runs of white pixels, as (code, length in bits, run length).
+/// Runs of 64 or more are made up of one or more makeup codes followed by a
+/// terminating code of 0 to 63.
+#[rustfmt::skip]
+const WHITE_CODES: [(u16, u8, u16); 104] = [
+    (0b00110101, 8, 0), (0b000111, 6, 1), (0b0111, 4, 2), (0b1000, 4, 3), (0b1011, 4, 4),
+    (0b1100, 4, 5), (0b1110, 4, 6), (0b1111, 4, 7), (0b10011, 5, 8), (0b10100, 5, 9),
+    (0b00111, 5, 10), (0b01000, 5, 11), (0b001000, 6, 12), (0b000011, 6, 13), (0b110100, 6, 14),
+    (0b110101, 6, 15), (0b101010, 6, 16), (0b101011, 6, 17), (0b0100111, 7, 18), (0b0001100, 7, 19),
+    (0b0001000, 7, 20), (0b0010111, 7, 21), (0b0000011, 7, 22), (0b0000100, 7, 23),
+    (0b0101000, 7, 24), (0b0101011, 7, 25), (0b0010011, 7, 26), (0b0100100, 7, 27),
+    (0b0011000, 7, 28), (0b00000010, 8, 29), (0b00000011, 8, 30), (0b00011010, 8, 31),
+    (0b00011011, 8, 32), (0b00010010, 8, 33), (0b00010011, 8, 34), (0b00010100, 8, 35),
+    (0b00010101, 8, 36), (0b00010110, 8, 37), (0b00010111, 8, 38), (0b00101000, 8, 39),
+    (0b00101001, 8, 40), (0b00101010, 8, 41), (0b00101011, 8, 42), (0b00101100, 8, 43),
+    (0b00101101, 8, 44), (0b00000100, 8, 45), (0b00000101, 8, 46), (0b00001010, 8, 47),
+    (0b00001011, 8, 48), (0b01010010, 8, 49), (0b01010011, 8, 50), (0b01010100, 8, 51),
+    (0b01010101, 8, 52), (0b00100100, 8, 53), (0b00100101, 8, 54), (0b01011000, 8, 55),
+    (0b01011001, 8, 56), (0b01011010, 8, 57), (0b01011011, 8, 58), (0b01001010, 8, 59),
+    (0b01001011, 8, 60), (0b00110010, 8, 61), (0b00110011, 8, 62), (0b00110100, 8, 63),
+    (0b11011, 5, 64), (0b10010, 5, 128), (0b010111, 6, 192), (0b0110111, 7, 256),
+    (0b00110110, 8, 320), (0b00110111, 8, 384), (0b01100100, 8, 448), (0b01100101, 8, 512),
+    (0b01101000, 8, 576), (0b01100111, 8, 640), (0b011001100, 9, 704), (0b011001101, 9, 768),
+    (0b011010010, 9, 832), (0b011010011, 9, 896), (0b011010100, 9, 960), (0b011010101, 9, 1024),
+    (0b011010110, 9, 1088), (0b011010111, 9, 1152), (0b011011000, 9, 1216), (0b011011001, 9, 1280),
+    (0b011011010, 9, 1344), (0b011011011, 9, 1408), (0b010011000, 9, 1472), (0b010011001, 9, 1536),
+    (0b010011010, 9, 1600), (0b011000, 6, 1664), (0b010011011, 9, 1728), (0b00000001000, 11, 1792),
+    (0b00000001100, 11, 1856), (0b00000001101, 11, 1920), (0b000000010010, 12, 1984),
+    (0b000000010011, 12, 2048), (0b000000010100, 12, 2112), (0b000000010101, 12, 2176),
+    (0b000000010110, 12, 2240), (0b000000010111, 12, 2304), (0b000000011100, 12, 2368),
+    (0b000000011101, 12, 2432), (0b000000011110, 12, 2496), (0b000000011111, 12, 2560),
+];
+/// The codes for runs of black pixels, laid out like [`WHITE_CODES`].
+#[rustfmt::skip]
+const BLACK_CODES: [(u16, u8, u16); 104] = [
+    (0b0000110111, 10, 0), (0b010, 3, 1), (0b11, 2, 2), (0b10, 2, 3), (0b011, 3, 4), (0b0011, 4, 5),
+    (0b0010, 4, 6), (0b00011, 5, 7), (0b000101, 6, 8), (0b000100, 6, 9), (0b0000100, 7, 10),
+    (0b0000101, 7, 11), (0b0000111, 7, 12), (0b00000100, 8, 13), (0b00000111, 8, 14),
+    (0b000011000, 9, 15), (0b0000010111, 10, 16), (0b0000011000, 10, 17), (0b0000001000, 10, 18),
+    (0b00001100111, 11, 19), (0b00001101000, 11, 20), (0b00001101100, 11, 21),
+    (0b00000110111, 11, 22), (0b00000101000, 11, 23), (0b00000010111, 11, 24),
+    (0b00000011000, 11, 25), (0b000011001010, 12, 26), (0b000011001011, 12, 27),
+    (0b000011001100, 12, 28), (0b000011001101, 12, 29), (0b000001101000, 12, 30),
+    (0b000001101001, 12, 31), (0b000001101010, 12, 32), (0b000001101011, 12, 33),
+    (0b000011010010, 12, 34), (0b000011010011, 12, 35), (0b000011010100, 12, 36),
+    (0b000011010101, 12, 37), (0b000011010110, 12, 38), (0b000011010111, 12, 39),
+    (0b000001101100, 12, 40), (0b000001101101, 12, 41), (0b000011011010, 12, 42),
+    (0b000011011011, 12, 43), (0b000001010100, 12, 44), (0b000001010101, 12, 45),
+    (0b000001010110, 12, 46), (0b000001010111, 12, 47), (0b000001100100, 12, 48),
+    (0b000001100101, 12, 49), (0b000001010010, 12, 50), (0b000001010011, 12, 51),
+    (0b000000100100, 12, 52), (0b000000110111, 12, 53), (0b000000111000, 12, 54),
+    (0b000000100111, 12, 55), (0b000000101000, 12, 56), (0b000001011000, 12, 57),
+    (0b000001011001, 12, 58), (0b000000101011, 12, 59), (0b000000101100, 12, 60),
+    (0b000001011010, 12, 61), (0b000001100110, 12, 62), (0b000001100111, 12, 63),
+    (0b0000001111, 10, 64), (0b000011001000, 12, 128), (0b000011001001, 12, 192),
+    (0b000001011011, 12, 256), (0b000000110011, 12, 320), (0b000000110100, 12, 384),
+    (0b000000110101, 12, 448), (0b0000001101100, 13, 512), (0b0000001101101, 13, 576),
+    (0b0000001001010, 13, 640), (0b0000001001011, 13, 704), (0b0000001001100, 13, 768),
+    (0b0000001001101, 13, 832), (0b0000001110010, 13, 896), (0b0000001110011, 13, 960),
+    (0b0000001110100, 13, 1024), (0b0000001110101, 13, 1088), (0b0000001110110, 13, 1152),
+    (0b0000001110111, 13, 1216), (0b0000001010010, 13, 1280), (0b0000001010011, 13, 1344),
+    (0b0000001010100, 13, 1408), (0b0000001010101, 13, 1472), (0b0000001011010, 13, 1536),
+    (0b0000001011011, 13, 1600), (0b0000001100100, 13, 1664), (0b0000001100101, 13, 1728),
+    (0b00000001000, 11, 1792), (0b00000001100, 11, 1856), (0b00000001101, 11, 1920),
+    (0b000000010010, 12, 1984), (0b000000010011, 12, 2048), (0b000000010100, 12, 2112),
+    (0b000000010101, 12, 2176), (0b000000010110, 12, 2240), (0b000000010111, 12, 2304),
+    (0b000000011100, 12, 2368), (0b000000011101, 12, 2432), (0b000000011110, 12, 2496),
+    (0b000000011111, 12, 2560),
+];
+
+/// The codes of the two-dimensional coding modes.
+const MODE_CODES: [(u32, usize, Mode); 9] = [
+    (0b1, 1, Mode::Vertical(0)),
+    (0b011, 3, Mode::Vertical(1)),
+    (0b010, 3, Mode::Vertical(-1)),
+    (0b001, 3, Mode::Horizontal),
+    (0b0001, 4, Mode::Pass),
+    (0b000011, 6, Mode::Vertical(2)),
+    (0b000010, 6, Mode::Vertical(-2)),
+    (0b0000011, 7, Mode::Vertical(3)),
+    (0b0000010, 7, Mode::Vertical(-3)),
+];
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Pass,
+    Horizontal,
+    Vertical(isize),
+}
+
+static WHITE_TABLE: OnceLock<Vec<(u16, u8)>> = OnceLock::new();
+static BLACK_TABLE: OnceLock<Vec<(u16, u8)>> = OnceLock::new();
+
+/// Indexes run length codes by the next `MAX_CODE_LEN` bits, giving the run
+/// length and the length of the code. Unused slots have a code length of 0.
+fn lookup_table(codes: &[(u16, u8, u16)]) -> Vec<(u16, u8)> {
+    let mut table = vec![(0, 0); 1 << MAX_CODE_LEN];
+    for &(code, len, run) in codes {
+        let shift = MAX_CODE_LEN - len as usize;
+        let start = (code as usize) << shift;
+        table[start..start + (1 << shift)].fill((run, len));
+    }
+    table
+}
+
+pub fn decode(data: &[u8], parms: Option<&Dictionary>, limit: usize) -> Result<Vec<u8>, PdfError> {
+    // K < 0 is Group 4; K = 0 is one-dimensional Group 3; K > 0 is Group 3
+    // with a bit before every line saying how it is coded
+    let k = int_parm(parms, "K", 0);
+    let columns = usize::try_from(int_parm(parms, "Columns", 1728))
+        .ok()
+        .filter(|columns| (1..=MAX_COLUMNS).contains(columns))
+        .ok_or_else(|| PdfError::filter("Invalid CCITTFaxDecode /Columns"))?;
+    let rows = usize::try_from(int_parm(parms, "Rows", 0)).unwrap_or(0);
+    let black_is_1 = bool_parm(parms, "BlackIs1", false);
+    let byte_align = bool_parm(parms, "EncodedByteAlign", false);
+    let end_of_block = bool_parm(parms, "EndOfBlock", true);
+    let mut bits = BitReader::new(data);
+    let decoder = Decoder {
+        columns,
+        white: WHITE_TABLE.get_or_init(|| lookup_table(&WHITE_CODES)),
+        black: BLACK_TABLE.get_or_init(|| lookup_table(&BLACK_CODES)),
+    };
+    let row_len = columns.div_ceil(8);
+    let mut output = vec![];
+    // the line above the first is white
+    let mut reference = vec![];
+    let mut decoded = 0;
+    while rows == 0 || decoded < rows {
+        let Some(two_dimensional) = decoder.start_line(&mut bits, k, byte_align, end_of_block)
+        else {
+            break;
+        };
+        let line = if two_dimensional {
+            decoder.decode_2d(&mut bits, &reference)
+        } else {
+            decoder.decode_1d(&mut bits)
+        };
+        let line = match line {
+            Ok(line) => line,
+            Err(e) if decoded == 0 => return Err(e),
+            // keep the rows before the damage
+            Err(_) => break,
+        };
+        let start = output.len();
+        if start + row_len > limit {
+            return Err(PdfError::filter("CCITTFaxDecode output is too large"));
+        }
+        output.resize(start + row_len, if black_is_1 { 0 } else { 0xFF });
+        decoder.render(&line, &mut output[start..], black_is_1);
+        reference = line;
+        decoded += 1;
+    }
+    Ok(output)
+}
+
+/// Lines are decoded into their changing elements: the columns where the
+/// color changes, starting with a change from white to black.
+struct Decoder {
+    columns: usize,
+    white: &'static [(u16, u8)],
+    black: &'static [(u16, u8)],
+}
+
+impl Decoder {
+    /// Reads what comes before a line: fill bits and EOL codes, and the bit
+    /// choosing one- or two-dimensional coding if there is one. Gives `None`
+    /// at the end of the data.
+    fn start_line(
+        &self,
+        bits: &mut BitReader,
+        k: i64,
+        byte_align: bool,
+        end_of_block: bool,
+    ) -> Option<bool> {
+        if k < 0 {
+            if byte_align {
+                bits.align();
+            }
+            if end_of_block && bits.peek(24) == END_OF_BLOCK {
+                return None;
+            }
+            return (bits.leading_zeros() < bits.remaining()).then_some(true);
+        }
+        let mut eols = 0;
+        while skip_eol(bits) {
+            eols += 1;
+        }
+        // six EOLs in a row end Group 3 data
+        if eols >= 2 && end_of_block {
+            return None;
+        }
+        if eols == 0 && byte_align {
+            bits.align();
+        }
+        if bits.leading_zeros() == bits.remaining() {
+            return None;
+        }
+        if k == 0 {
+            return Some(false);
+        }
+        let two_dimensional = bits.read(1)? == 0;
+        // in mixed data, the EOLs are each followed by a 1 bit
+        if end_of_block && skip_eol(bits) {
+            return None;
+        }
+        Some(two_dimensional)
+    }
+    fn decode_1d(&self, bits: &mut BitReader) -> Result<Vec<usize>, PdfError> {
+        let mut changes = vec![];
+        let mut position = 0;
+        let mut black = false;
+        while position < self.columns {
+            position = (position + self.read_run(bits, black)?).min(self.columns);
+            changes.push(position);
+            black = !black;
+        }
+        Ok(changes)
+    }
+    /// Decodes a line coded relative to the line above, `reference`.
+    fn decode_2d(&self, bits: &mut BitReader, reference: &[usize]) -> Result<Vec<usize>, PdfError> {
+        let columns = self.columns;
+        let mut changes = vec![];
+        // `None` is the imaginary white pixel before the start of the line
+        let mut a0: Option<usize> = None;
+        let mut black = false;
+        while a0.is_none_or(|a0| a0 < columns) {
+            let start = a0.unwrap_or(0);
+            // b1 is the first change on the reference line to the right of
+            // a0 that changes to the opposite of the current color, and b2
+            // the change after it; changes to black have even indices
+            let mut i = reference.partition_point(|&x| a0.is_some_and(|a0| x <= a0));
+            if (i % 2 == 1) != black {
+                i += 1;
+            }
+            let b1 = reference.get(i).map_or(columns, |&x| x.min(columns));
+            let b2 = reference.get(i + 1).map_or(columns, |&x| x.min(columns));
+            match read_mode(bits)? {
+                Mode::Pass => a0 = Some(b2),
+                Mode::Horizontal => {
+                    let a1 = (start + self.read_run(bits, black)?).min(columns);
+                    let a2 = (a1 + self.read_run(bits, !black)?).min(columns);
+                    changes.extend([a1, a2]);
+                    a0 = Some(a2);
+                }
+                Mode::Vertical(offset) => {
+                    let a1 = b1
+                        .checked_add_signed(offset)
+                        .filter(|a1| (start..=columns).contains(a1))
+                        .ok_or_else(|| PdfError::filter("CCITT vertical mode is out of range"))?;
+                    changes.push(a1);
+                    a0 = Some(a1);
+                    black = !black;
+                }
+            }
+        }
+        Ok(changes)
+    }
+    /// Reads a run length: any makeup codes, then a terminating code.
+    fn read_run(&self, bits: &mut BitReader, black: bool) -> Result<usize, PdfError> {
+        let table = if black { self.black } else { self.white };
+        let mut run = 0;
+        loop {
+            let (length, len) = table[bits.peek(MAX_CODE_LEN) as usize];
+            if len == 0 || bits.remaining() < len as usize {
+                return Err(PdfError::filter("Invalid CCITT run length code"));
+            }
+            bits.skip(len as usize);
+            run += length as usize;
+            if length < 64 {
+                return Ok(run);
+            }
+        }
+    }
+    /// Packs a line into a row of the bitmap, which starts out white.
+    fn render(&self, changes: &[usize], row: &mut [u8], black_is_1: bool) {
+        for span in changes.chunks(2) {
+            let end = span.get(1).copied().unwrap_or(self.columns);
+            for x in span[0]..end.min(self.columns) {
+                if black_is_1 {
+                    row[x / 8] |= 0x80 >> (x % 8);
+                } else {
+                    row[x / 8] &= !(0x80 >> (x % 8));
+                }
+            }
+        }
+    }
+}
+
+fn read_mode(bits: &mut BitReader) -> Result<Mode, PdfError> {
+    for (code, len, mode) in MODE_CODES {
+        if bits.remaining() >= len && bits.peek(len) == code {
+            bits.skip(len);
+            return Ok(mode);
+        }
+    }
+    Err(PdfError::filter("Invalid CCITT mode code"))
+}
+
+/// Skips an EOL code (eleven or more 0 bits, then a 1) if one is next.
+fn skip_eol(bits: &mut BitReader) -> bool {
+    let zeros = bits.leading_zeros();
+    if zeros < 11 || zeros == bits.remaining() {
+        return false;
+    }
+    bits.skip(zeros + 1);
+    true
+}
@@ -0,0 +1,54 @@
+//! DCTDecode: baseline and progressive JPEG, decoded to raw samples.
+use crate::error::PdfError;
+use crate::structure::Dictionary;
+use jpeg_decoder::{ColorTransform, Decoder, PixelFormat};
+
+/// Decodes to interleaved 8-bit samples, row by row. Four-component images
+/// give CMYK exactly as stored, so that Adobe's inverted CMYK stays inverted
+/// for the image's /Decode array to undo.
+pub fn decode(data: &[u8], parms: Option<&Dictionary>, limit: usize) -> Result<Vec<u8>, PdfError> {
+    let jpeg_error = |e| PdfError::filter(format!("DCTDecode failed: {}", e));
+    let mut decoder = Decoder::new(data);
+    decoder.set_max_decoding_buffer_size(limit);
+    decoder.read_info().map_err(jpeg_error)?;
+    let Some(info) = decoder.info() else {
+        return Err(PdfError::filter("DCTDecode data has no frame header"));
+    };
+    // an Adobe APP14 marker names the transform itself, and wins over
+    // /ColorTransform
+    if !has_adobe_marker(data) {
+        if let Some(transform) = parms.and_then(|parms| parms.get_int("ColorTransform").ok()) {
+            decoder.set_color_transform(match (info.pixel_format, transform) {
+                (PixelFormat::RGB24, 0) => ColorTransform::RGB,
+                (PixelFormat::RGB24, _) => ColorTransform::YCbCr,
+                (PixelFormat::CMYK32, 0) => ColorTransform::CMYK,
+                (PixelFormat::CMYK32, _) => ColorTransform::YCCK,
+                _ => ColorTransform::Grayscale,
+            });
+        }
+    }
+    let mut samples = decoder.decode().map_err(jpeg_error)?;
+    match info.pixel_format {
+        PixelFormat::L8 | PixelFormat::RGB24 => {}
+        // the decoder undoes Adobe's inversion, so invert again
+        PixelFormat::CMYK32 => samples.iter_mut().for_each(|sample| *sample = !*sample),
+        PixelFormat::L16 => return Err(PdfError::filter("Unsupported 16-bit DCTDecode data")),
+    }
+    Ok(samples)
+}
+
+/// Whether the marker segments before the image data include Adobe's APP14.
+fn has_adobe_marker(data: &[u8]) -> bool {
+    let mut position = 2;
+    while let Some(&[0xFF, marker, high, low]) = data.get(position..position + 4) {
+        match marker {
+            // start of scan
+            0xDA => break,
+            // fill bytes
+            0xFF => position += 1,
+            0xEE if data[position + 4..].starts_with(b"Adobe") => return true,
+            _ => position += 2 + u16::from_be_bytes([high, low]) as usize,
+        }
+    }
+    false
+}
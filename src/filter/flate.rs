@@ -0,0 +1,48 @@
+//! FlateDecode: zlib compressed data.
+use super::predictor;
+use crate::error::PdfError;
+use crate::structure::Dictionary;
+use flate2::{Decompress, FlushDecompress, Status};
+
+pub fn decode(data: &[u8], parms: Option<&Dictionary>, limit: usize) -> Result<Vec<u8>, PdfError> {
+    let decoded = match inflate(data, true, limit) {
+        Ok((decoded, _)) => decoded,
+        // some writers leave out the zlib header; as anything inflates to
+        // something, only take the result if it ends properly
+        Err(e) => match inflate(data, false, limit) {
+            Ok((decoded, true)) => decoded,
+            _ => return Err(e),
+        },
+    };
+    predictor::unpredict(decoded, parms)
+}
+
+/// Inflates as much of `data` as possible, and says whether it reached the
+/// end of the compressed data. Truncated or corrupt data gives whatever could
+/// be decoded before the damage; only data which decodes to nothing at all,
+/// or to more than `limit` bytes, is an error.
+fn inflate(data: &[u8], zlib_header: bool, limit: usize) -> Result<(Vec<u8>, bool), PdfError> {
+    let mut inflater = Decompress::new(zlib_header);
+    let capacity = data.len().saturating_mul(4).clamp(64, limit.max(64));
+    let mut output = Vec::with_capacity(capacity);
+    loop {
+        if output.len() == output.capacity() {
+            if output.len() >= limit {
+                return Err(PdfError::filter("FlateDecode output is too large"));
+            }
+            output.reserve_exact(output.len().min(limit - output.len()));
+        }
+        let (read, written) = (inflater.total_in(), inflater.total_out());
+        let input = data.get(read as usize..).unwrap_or_default();
+        match inflater.decompress_vec(input, &mut output, FlushDecompress::None) {
+            Ok(Status::StreamEnd) => return Ok((output, true)),
+            Ok(_) if inflater.total_in() == read && inflater.total_out() == written => break,
+            Ok(_) => {}
+            Err(e) if output.is_empty() => {
+                return Err(PdfError::filter(format!("FlateDecode failed: {}", e)))
+            }
+            Err(_) => break,
+        }
+    }
+    Ok((output, false))
+}
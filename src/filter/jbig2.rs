@@ -0,0 +1,859 @@
+//! JBIG2Decode: the bilevel image compression of ITU-T T.88, in the embedded
+//! form PDF uses, decoding to a 1-bit image. Generic regions, symbol
+//! dictionaries and the text regions that place their symbols are supported;
+//! Huffman coding, refinement and halftones are not.
+use super::{ccitt, DecodeOptions};
+use crate::error::PdfError;
+use crate::structure::{AnyPDFData, Dictionary};
+use std::collections::HashMap;
+use std::mem;
+
+/// The probability estimates of the MQ coder, as (Qe, next index after an
+/// MPS, next index after an LPS, whether an LPS switches the MPS).
+#[rustfmt::skip]
+const QE_TABLE: [(u32, u8, u8, bool); 47] = [
+    (0x5601, 1, 1, true), (0x3401, 2, 6, false), (0x1801, 3, 9, false), (0x0AC1, 4, 12, false),
+    (0x0521, 5, 29, false), (0x0221, 38, 33, false), (0x5601, 7, 6, true), (0x5401, 8, 14, false),
+    (0x4801, 9, 14, false), (0x3801, 10, 14, false), (0x3001, 11, 17, false), (0x2401, 12, 18, false),
+    (0x1C01, 13, 20, false), (0x1601, 29, 21, false), (0x5601, 15, 14, true), (0x5401, 16, 14, false),
+    (0x5101, 17, 15, false), (0x4801, 18, 16, false), (0x3801, 19, 17, false), (0x3401, 20, 18, false),
+    (0x3001, 21, 19, false), (0x2801, 22, 19, false), (0x2401, 23, 20, false), (0x2201, 24, 21, false),
+    (0x1C01, 25, 22, false), (0x1801, 26, 23, false), (0x1601, 27, 24, false), (0x1401, 28, 25, false),
+    (0x1201, 29, 26, false), (0x1101, 30, 27, false), (0x0AC1, 31, 28, false), (0x09C1, 32, 29, false),
+    (0x08A1, 33, 30, false), (0x0521, 34, 31, false), (0x0441, 35, 32, false), (0x02A1, 36, 33, false),
+    (0x0221, 37, 34, false), (0x0141, 38, 35, false), (0x0111, 39, 36, false), (0x0085, 40, 37, false),
+    (0x0049, 41, 38, false), (0x0025, 42, 39, false), (0x0015, 43, 40, false), (0x0009, 44, 41, false),
+    (0x0005, 45, 42, false), (0x0001, 45, 43, false), (0x5601, 46, 46, false),
+];
+
+/// How many times the arithmetic decoder may feed padding, past the end of
+/// its data or at a marker, before the data is taken to have run out. Data
+/// which is properly terminated needs a few at most.
+const MAX_PADDING: usize = 64;
+
+/// The context in which TPGDON codes whether a row repeats the one above,
+/// for each generic region template.
+const TYPICAL_CONTEXTS: [usize; 4] = [0x9B25, 0x0795, 0x00E5, 0x0195];
+
+pub fn decode(data: &[u8], parms: Option<&Dictionary>, limit: usize) -> Result<Vec<u8>, PdfError> {
+    let mut decoder = Decoder {
+        limit,
+        ..Decoder::default()
+    };
+    match parms.and_then(|parms| parms.get("JBIG2Globals")) {
+        None | Some(AnyPDFData::Null) => {}
+        Some(AnyPDFData::Stream(globals)) => {
+            let options = DecodeOptions {
+                max_decoded_len: limit,
+                ..DecodeOptions::default()
+            };
+            decoder.read_segments(&globals.decoded_with(options)?)?
+        }
+        Some(AnyPDFData::ObjRef(..)) => {
+            return Err(PdfError::resolution(
+                "/JBIG2Globals is a reference; decode the stream through its document",
+            ))
+        }
+        Some(other) => {
+            return Err(PdfError::syntax(format!(
+                "Expected /JBIG2Globals to be a stream, found {}",
+                other.type_name()
+            )))
+        }
+    }
+    decoder.read_segments(data)?;
+    match decoder.page {
+        Some(page) => Ok(page.pack()),
+        None => Err(PdfError::filter("JBIG2 data has no page information")),
+    }
+}
+
+fn truncated() -> PdfError {
+    PdfError::filter("JBIG2 segment is truncated")
+}
+
+fn unsupported(what: &str) -> PdfError {
+    PdfError::filter(format!("Unsupported JBIG2 feature: {}", what))
+}
+
+/// Reads the big-endian fields of segment headers and data.
+struct Bytes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], PdfError> {
+        if count > self.data.len() {
+            return Err(truncated());
+        }
+        let (taken, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, PdfError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, PdfError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32, PdfError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    /// Adaptive template pixels: `count` pairs of signed offsets.
+    fn at_pixels(&mut self, count: usize) -> Result<Vec<(isize, isize)>, PdfError> {
+        (0..count)
+            .map(|_| Ok((self.u8()? as i8 as isize, self.u8()? as i8 as isize)))
+            .collect()
+    }
+}
+
+/// A bilevel image with a byte per pixel, 1 for black.
+#[derive(Clone)]
+struct Bitmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Bitmap {
+    /// The memory the bitmap takes, a byte per pixel and the struct itself.
+    fn footprint(&self) -> usize {
+        self.pixels.len().saturating_add(mem::size_of::<Bitmap>())
+    }
+    fn get(&self, x: isize, y: isize) -> u8 {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return 0;
+        }
+        self.pixels[y as usize * self.width + x as usize]
+    }
+    /// Draws `other` with its top left corner at (`x`, `y`), combining the
+    /// pixels with OR, AND, XOR, XNOR or REPLACE. Only the part of `other`
+    /// which lands on this bitmap is visited.
+    fn compose(&mut self, other: &Bitmap, x: isize, y: isize, operator: u8) {
+        let clip = |offset: isize, len: usize, bound: usize| {
+            let start = offset.min(0).unsigned_abs().min(len);
+            let end = (bound as isize)
+                .saturating_sub(offset)
+                .clamp(0, len as isize) as usize;
+            start..end.max(start)
+        };
+        for row in clip(y, other.height, self.height) {
+            for column in clip(x, other.width, self.width) {
+                let (px, py) = (x + column as isize, y + row as isize);
+                let target = &mut self.pixels[py as usize * self.width + px as usize];
+                let source = other.pixels[row * other.width + column];
+                *target = match operator {
+                    0 => *target | source,
+                    1 => *target & source,
+                    2 => *target ^ source,
+                    3 => 1 ^ *target ^ source,
+                    _ => source,
+                };
+            }
+        }
+    }
+    /// Packs the rows, with 0 for black as in DeviceGray.
+    fn pack(&self) -> Vec<u8> {
+        let row_len = self.width.div_ceil(8);
+        let mut packed = vec![0xFF; row_len * self.height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.pixels[y * self.width + x] == 1 {
+                    packed[y * row_len + x / 8] &= !(0x80 >> (x % 8));
+                }
+            }
+        }
+        packed
+    }
+}
+
+/// The MQ arithmetic decoder. A context is a byte holding the index into
+/// `QE_TABLE` shifted left by one, and the current MPS in the low bit.
+struct ArithmeticDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    c: u32,
+    a: u32,
+    ct: u32,
+    padding: usize,
+}
+
+impl<'a> ArithmeticDecoder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        let mut decoder = ArithmeticDecoder {
+            data,
+            position: 0,
+            c: 0,
+            a: 0x8000,
+            ct: 0,
+            padding: 0,
+        };
+        decoder.c = (decoder.byte(0) as u32) << 16;
+        decoder.byte_in();
+        decoder.c <<= 7;
+        decoder.ct -= 7;
+        decoder
+    }
+    /// The data is taken to be followed by 0xFF bytes.
+    fn byte(&self, position: usize) -> u8 {
+        self.data.get(position).copied().unwrap_or(0xFF)
+    }
+    fn byte_in(&mut self) {
+        if self.byte(self.position) == 0xFF {
+            if self.byte(self.position + 1) > 0x8F {
+                // a marker: feed 1 bits without moving past it
+                self.c = self.c.wrapping_add(0xFF00);
+                self.ct = 8;
+                self.padding += 1;
+            } else {
+                self.position += 1;
+                self.c = self.c.wrapping_add((self.byte(self.position) as u32) << 9);
+                self.ct = 7;
+            }
+        } else {
+            self.position += 1;
+            self.c = self.c.wrapping_add((self.byte(self.position) as u32) << 8);
+            self.ct = 8;
+        }
+    }
+    fn decode(&mut self, context: &mut u8) -> u8 {
+        let (index, mps) = ((*context >> 1) as usize, *context & 1);
+        let (qe, next_mps, next_lps, switch) = QE_TABLE[index.min(QE_TABLE.len() - 1)];
+        self.a -= qe;
+        let (bit, next) = if (self.c >> 16) < qe {
+            let exchange = self.a < qe;
+            self.a = qe;
+            if exchange {
+                (mps, next_mps)
+            } else {
+                (1 - mps, next_lps)
+            }
+        } else {
+            self.c -= qe << 16;
+            if self.a & 0x8000 != 0 {
+                return mps;
+            }
+            if self.a < qe {
+                (1 - mps, next_lps)
+            } else {
+                (mps, next_mps)
+            }
+        };
+        let mps = if bit != mps && switch { bit } else { mps };
+        *context = next << 1 | mps;
+        loop {
+            if self.ct == 0 {
+                self.byte_in();
+            }
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+            if self.a & 0x8000 != 0 {
+                break;
+            }
+        }
+        bit
+    }
+    /// Fails once the data has run out, which would otherwise leave hostile
+    /// data decoding padding for ever.
+    fn check(&self) -> Result<(), PdfError> {
+        if self.padding > MAX_PADDING {
+            return Err(PdfError::filter(
+                "JBIG2 arithmetic coded data ends too early",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Decodes signed integers, or `None` for the out-of-band value.
+struct IntegerDecoder {
+    contexts: Vec<u8>,
+}
+
+impl IntegerDecoder {
+    fn new() -> Self {
+        IntegerDecoder {
+            contexts: vec![0; 512],
+        }
+    }
+    fn decode(&mut self, decoder: &mut ArithmeticDecoder) -> Option<i64> {
+        let mut previous = 1;
+        let mut bit = || {
+            let bit = decoder.decode(&mut self.contexts[previous]);
+            previous = if previous < 256 {
+                previous << 1 | bit as usize
+            } else {
+                (previous << 1 | bit as usize) & 511 | 256
+            };
+            bit
+        };
+        let sign = bit();
+        let (len, offset) = if bit() == 0 {
+            (2, 0)
+        } else if bit() == 0 {
+            (4, 4)
+        } else if bit() == 0 {
+            (6, 20)
+        } else if bit() == 0 {
+            (8, 84)
+        } else if bit() == 0 {
+            (12, 340)
+        } else {
+            (32, 4436)
+        };
+        let value = (0..len).fold(0i64, |value, _| value << 1 | bit() as i64) + offset;
+        match (sign, value) {
+            (0, value) => Some(value),
+            (_, 0) => None,
+            (_, value) => Some(-value),
+        }
+    }
+}
+
+/// Decodes symbol IDs, which are coded in a fixed number of bits.
+struct IdDecoder {
+    len: u32,
+    contexts: Vec<u8>,
+}
+
+impl IdDecoder {
+    fn new(len: u32) -> Self {
+        IdDecoder {
+            len,
+            contexts: vec![0; 2 << len],
+        }
+    }
+    fn decode(&mut self, decoder: &mut ArithmeticDecoder) -> usize {
+        let mut previous = 1;
+        for _ in 0..self.len {
+            previous = previous << 1 | decoder.decode(&mut self.contexts[previous]) as usize;
+        }
+        previous - (1 << self.len)
+    }
+}
+
+/// The pixels a generic region template looks at, most significant bit of
+/// the context first, with the adaptive pixels `at` in their places.
+#[rustfmt::skip]
+fn template_pixels(template: u8, at: &[(isize, isize)]) -> Vec<(isize, isize)> {
+    let at = |i: usize| at[i];
+    match template {
+        0 => vec![
+            at(3), (-1, -2), (0, -2), (1, -2), at(2),
+            at(1), (-2, -1), (-1, -1), (0, -1), (1, -1), (2, -1), at(0),
+            (-4, 0), (-3, 0), (-2, 0), (-1, 0),
+        ],
+        1 => vec![
+            (-1, -2), (0, -2), (1, -2), (2, -2),
+            (-2, -1), (-1, -1), (0, -1), (1, -1), (2, -1), at(0),
+            (-3, 0), (-2, 0), (-1, 0),
+        ],
+        2 => vec![
+            (-1, -2), (0, -2), (1, -2),
+            (-2, -1), (-1, -1), (0, -1), (1, -1), at(0),
+            (-2, 0), (-1, 0),
+        ],
+        _ => vec![
+            (-3, -1), (-2, -1), (-1, -1), (0, -1), (1, -1), at(0),
+            (-4, 0), (-3, 0), (-2, 0), (-1, 0),
+        ],
+    }
+}
+
+/// The contexts of generic region decoding, which symbol dictionaries share
+/// between all their symbols.
+fn generic_contexts(template: u8) -> Vec<u8> {
+    let bits = match template {
+        0 => 16,
+        1 => 13,
+        _ => 10,
+    };
+    vec![0; 1 << bits]
+}
+
+/// The fields at the start of every region segment.
+struct RegionInfo {
+    width: usize,
+    height: usize,
+    x: isize,
+    y: isize,
+    operator: u8,
+}
+
+impl RegionInfo {
+    fn read(bytes: &mut Bytes) -> Result<Self, PdfError> {
+        Ok(RegionInfo {
+            width: bytes.u32()? as usize,
+            height: bytes.u32()? as usize,
+            x: bytes.u32()? as i32 as isize,
+            y: bytes.u32()? as i32 as isize,
+            operator: bytes.u8()? & 7,
+        })
+    }
+}
+
+struct SegmentHeader {
+    number: u32,
+    kind: u8,
+    referred: Vec<u32>,
+    data_len: u32,
+}
+
+impl SegmentHeader {
+    fn read(bytes: &mut Bytes) -> Result<Self, PdfError> {
+        let number = bytes.u32()?;
+        let flags = bytes.u8()?;
+        let first = bytes.u8()?;
+        let count = match first >> 5 {
+            // the long form: a 29-bit count and a bit per segment
+            7 => {
+                let rest = bytes.take(3)?;
+                let count = u32::from_be_bytes([first & 0x1F, rest[0], rest[1], rest[2]]) as usize;
+                bytes.take((count + 1).div_ceil(8))?;
+                count
+            }
+            5 | 6 => return Err(PdfError::filter("Invalid JBIG2 referred segment count")),
+            count => count as usize,
+        };
+        let referred = (0..count)
+            .map(|_| match number {
+                0..=256 => bytes.u8().map(u32::from),
+                257..=65536 => bytes.u16().map(u32::from),
+                _ => bytes.u32(),
+            })
+            .collect::<Result<_, _>>()?;
+        // the page the segment belongs to; embedded data has just one
+        if flags & 0x40 != 0 {
+            bytes.u32()?;
+        } else {
+            bytes.u8()?;
+        }
+        Ok(SegmentHeader {
+            number,
+            kind: flags & 0x3F,
+            referred,
+            data_len: bytes.u32()?,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Decoder {
+    /// The symbols each symbol dictionary exports, by segment number.
+    symbols: HashMap<u32, Vec<Bitmap>>,
+    page: Option<Bitmap>,
+    /// Whether the page grows with its regions, its height being unknown.
+    striped: bool,
+    default_pixel: u8,
+    /// The memory taken by every bitmap made so far, which `limit` bounds.
+    allocated: usize,
+    /// The most data any filter this one goes through may decode to.
+    limit: usize,
+}
+
+impl Decoder {
+    fn read_segments(&mut self, data: &[u8]) -> Result<(), PdfError> {
+        let mut bytes = Bytes { data };
+        while !bytes.data.is_empty() {
+            let header = SegmentHeader::read(&mut bytes)?;
+            if header.data_len == u32::MAX {
+                return Err(unsupported("segments of unknown length"));
+            }
+            let data = bytes.take(header.data_len as usize)?;
+            match header.kind {
+                0 => {
+                    let input = self.referred_symbols(&header)?;
+                    let exported = self.symbol_dictionary(data, input)?;
+                    self.symbols.insert(header.number, exported);
+                }
+                6 | 7 => {
+                    let symbols = self.referred_symbols(&header)?;
+                    let (info, region) = self.text_region(data, &symbols)?;
+                    self.draw(&info, &region)?;
+                }
+                38 | 39 => {
+                    let (info, region) = self.generic_region(data)?;
+                    self.draw(&info, &region)?;
+                }
+                48 => self.page_information(data)?,
+                50 => {
+                    // the end of a stripe of a page of unknown height
+                    let end = Bytes { data }.u32()? as usize;
+                    self.grow(end.saturating_add(1))?;
+                }
+                51 => break,
+                // intermediate regions only matter to refinement; the rest
+                // are end of page, profiles, tables and extensions
+                4 | 36 | 49 | 52 | 53 | 62 => {}
+                16 | 20 | 22 | 23 => return Err(unsupported("halftone regions")),
+                40 | 42 | 43 => return Err(unsupported("refinement regions")),
+                kind => {
+                    return Err(PdfError::filter(format!(
+                        "Unknown JBIG2 segment type {}",
+                        kind
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Counts `len` more bytes of bitmaps against the limit.
+    fn charge(&mut self, len: usize) -> Result<(), PdfError> {
+        self.allocated = self
+            .allocated
+            .checked_add(len)
+            .filter(|&allocated| allocated <= self.limit)
+            .ok_or_else(|| PdfError::filter("JBIG2 image is too large"))?;
+        Ok(())
+    }
+    fn bitmap(&mut self, width: usize, height: usize) -> Result<Bitmap, PdfError> {
+        // the struct counts too, so that empty symbols cannot be made
+        // without limit either
+        let pixels = width
+            .checked_mul(height)
+            .ok_or_else(|| PdfError::filter("JBIG2 image is too large"))?;
+        self.charge(pixels.saturating_add(mem::size_of::<Bitmap>()))?;
+        Ok(Bitmap {
+            width,
+            height,
+            pixels: vec![0; pixels],
+        })
+    }
+    /// Makes a page of unknown height at least `height` rows tall.
+    fn grow(&mut self, height: usize) -> Result<(), PdfError> {
+        let Some(page) = self.page.as_ref().filter(|_| self.striped) else {
+            return Ok(());
+        };
+        if height <= page.height {
+            return Ok(());
+        }
+        let added = page
+            .width
+            .checked_mul(height - page.height)
+            .ok_or_else(|| PdfError::filter("JBIG2 image is too large"))?;
+        self.charge(added)?;
+        if let Some(page) = self.page.as_mut() {
+            page.pixels
+                .resize(page.pixels.len() + added, self.default_pixel);
+            page.height = height;
+        }
+        Ok(())
+    }
+    /// Copies the symbols of the dictionaries a segment refers to, which
+    /// count against the limit like any other bitmap.
+    fn referred_symbols(&mut self, header: &SegmentHeader) -> Result<Vec<Bitmap>, PdfError> {
+        let len = header
+            .referred
+            .iter()
+            .filter_map(|number| self.symbols.get(number))
+            .flatten()
+            .fold(0, |len: usize, symbol| {
+                len.saturating_add(symbol.footprint())
+            });
+        self.charge(len)?;
+        Ok(header
+            .referred
+            .iter()
+            .filter_map(|number| self.symbols.get(number))
+            .flatten()
+            .cloned()
+            .collect())
+    }
+    fn page_information(&mut self, data: &[u8]) -> Result<(), PdfError> {
+        let mut bytes = Bytes { data };
+        let width = bytes.u32()? as usize;
+        let height = bytes.u32()?;
+        bytes.take(8)?;
+        let flags = bytes.u8()?;
+        self.striped = height == u32::MAX;
+        let height = if self.striped { 0 } else { height as usize };
+        self.default_pixel = flags >> 2 & 1;
+        let mut page = self.bitmap(width, height)?;
+        page.pixels.fill(self.default_pixel);
+        self.page = Some(page);
+        Ok(())
+    }
+    fn draw(&mut self, info: &RegionInfo, region: &Bitmap) -> Result<(), PdfError> {
+        if self.page.is_none() {
+            return Err(PdfError::filter(
+                "JBIG2 region comes before the page information",
+            ));
+        }
+        let bottom = usize::try_from(info.y)
+            .unwrap_or(0)
+            .saturating_add(region.height);
+        self.grow(bottom)?;
+        if let Some(page) = self.page.as_mut() {
+            page.compose(region, info.x, info.y, info.operator);
+        }
+        Ok(())
+    }
+    fn generic_region(&mut self, data: &[u8]) -> Result<(RegionInfo, Bitmap), PdfError> {
+        let mut bytes = Bytes { data };
+        let info = RegionInfo::read(&mut bytes)?;
+        let flags = bytes.u8()?;
+        let template = flags >> 1 & 3;
+        if flags & 0x10 != 0 {
+            return Err(unsupported("extended templates"));
+        }
+        if flags & 1 != 0 {
+            let region = self.mmr(bytes.data, info.width, info.height)?;
+            return Ok((info, region));
+        }
+        let at = bytes.at_pixels(if template == 0 { 4 } else { 1 })?;
+        let mut decoder = ArithmeticDecoder::new(bytes.data);
+        let mut contexts = generic_contexts(template);
+        let typical = flags & 8 != 0;
+        let region = self.generic(
+            &mut decoder,
+            &mut contexts,
+            (info.width, info.height),
+            template,
+            typical,
+            &at,
+        )?;
+        Ok((info, region))
+    }
+    /// Generic region data coded with MMR, which is Group 4 fax coding with
+    /// 1 for black.
+    fn mmr(&mut self, data: &[u8], width: usize, height: usize) -> Result<Bitmap, PdfError> {
+        let mut bitmap = self.bitmap(width, height)?;
+        if width == 0 || height == 0 {
+            return Ok(bitmap);
+        }
+        let parms: Dictionary = vec![
+            ("K", AnyPDFData::Integer(-1)),
+            ("Columns", AnyPDFData::Integer(width as i64)),
+            ("Rows", AnyPDFData::Integer(height as i64)),
+            ("BlackIs1", AnyPDFData::Boolean(true)),
+        ]
+        .into();
+        let rows = ccitt::decode(data, Some(&parms), self.limit)?;
+        let row_len = width.div_ceil(8);
+        for (y, row) in rows.chunks(row_len).enumerate().take(height) {
+            for x in 0..width {
+                bitmap.pixels[y * width + x] = row[x / 8] >> (7 - x % 8) & 1;
+            }
+        }
+        Ok(bitmap)
+    }
+    /// Decodes an arithmetic coded generic region of `size` pixels. With
+    /// `typical`, each row starts with a bit saying whether it repeats the
+    /// row above.
+    fn generic(
+        &mut self,
+        decoder: &mut ArithmeticDecoder,
+        contexts: &mut [u8],
+        (width, height): (usize, usize),
+        template: u8,
+        typical: bool,
+        at: &[(isize, isize)],
+    ) -> Result<Bitmap, PdfError> {
+        let mut bitmap = self.bitmap(width, height)?;
+        let pixels = template_pixels(template, at);
+        let mut repeat = false;
+        for y in 0..height {
+            decoder.check()?;
+            if typical {
+                repeat ^= decoder.decode(&mut contexts[TYPICAL_CONTEXTS[template as usize]]) == 1;
+                if repeat {
+                    if y > 0 {
+                        bitmap
+                            .pixels
+                            .copy_within((y - 1) * width..y * width, y * width);
+                    }
+                    continue;
+                }
+            }
+            for x in 0..width {
+                let context = pixels.iter().fold(0, |context, &(dx, dy)| {
+                    context << 1 | bitmap.get(x as isize + dx, y as isize + dy) as usize
+                });
+                bitmap.pixels[y * width + x] = decoder.decode(&mut contexts[context]);
+            }
+        }
+        Ok(bitmap)
+    }
+    /// Decodes the symbols of a symbol dictionary, and gives those it
+    /// exports, which may include some of the `input` symbols.
+    fn symbol_dictionary(
+        &mut self,
+        data: &[u8],
+        input: Vec<Bitmap>,
+    ) -> Result<Vec<Bitmap>, PdfError> {
+        let mut bytes = Bytes { data };
+        let flags = bytes.u16()?;
+        if flags & 1 != 0 {
+            return Err(unsupported("Huffman coded symbol dictionaries"));
+        }
+        if flags & 2 != 0 {
+            return Err(unsupported("refinement and aggregate coded symbols"));
+        }
+        if flags & 0x100 != 0 {
+            return Err(unsupported("symbol dictionaries sharing coding contexts"));
+        }
+        let template = (flags >> 10 & 3) as u8;
+        let at = bytes.at_pixels(if template == 0 { 4 } else { 1 })?;
+        let exported_count = bytes.u32()? as usize;
+        let new_count = bytes.u32()? as usize;
+        let mut decoder = ArithmeticDecoder::new(bytes.data);
+        let mut contexts = generic_contexts(template);
+        let (mut height_deltas, mut width_deltas, mut export_runs) = (
+            IntegerDecoder::new(),
+            IntegerDecoder::new(),
+            IntegerDecoder::new(),
+        );
+        let invalid = || PdfError::filter("Invalid JBIG2 symbol dictionary");
+        let mut symbols = input;
+        let input_count = symbols.len();
+        let mut height = 0i64;
+        // symbols come in classes of equal height; as a class may be empty,
+        // the data bounds their number
+        let mut classes = 0;
+        while symbols.len() - input_count < new_count {
+            decoder.check()?;
+            classes += 1;
+            if classes > data.len().saturating_mul(8) {
+                return Err(invalid());
+            }
+            let delta = height_deltas.decode(&mut decoder).ok_or_else(invalid)?;
+            height = height.checked_add(delta).ok_or_else(invalid)?;
+            let mut width = 0i64;
+            // each class ends with an out-of-band width
+            while let Some(delta) = width_deltas.decode(&mut decoder) {
+                decoder.check()?;
+                width = width.checked_add(delta).ok_or_else(invalid)?;
+                let (Ok(width), Ok(height)) = (usize::try_from(width), usize::try_from(height))
+                else {
+                    return Err(invalid());
+                };
+                if symbols.len() - input_count == new_count {
+                    return Err(invalid());
+                }
+                let symbol = self.generic(
+                    &mut decoder,
+                    &mut contexts,
+                    (width, height),
+                    template,
+                    false,
+                    &at,
+                )?;
+                symbols.push(symbol);
+            }
+        }
+        // which symbols are exported, as alternating runs of excluded and
+        // exported symbols
+        let mut exported = vec![];
+        let mut index = 0;
+        let mut export = false;
+        while index < symbols.len() {
+            decoder.check()?;
+            let run = export_runs
+                .decode(&mut decoder)
+                .and_then(|run| usize::try_from(run).ok())
+                .filter(|&run| run <= symbols.len() - index)
+                .ok_or_else(invalid)?;
+            if export {
+                let run = &symbols[index..index + run];
+                let len = run.iter().fold(0, |len: usize, symbol| {
+                    len.saturating_add(symbol.footprint())
+                });
+                self.charge(len)?;
+                exported.extend_from_slice(run);
+            }
+            index += run;
+            export = !export;
+        }
+        if exported.len() != exported_count {
+            return Err(invalid());
+        }
+        Ok(exported)
+    }
+    /// Decodes a text region, which places symbols along strips.
+    fn text_region(
+        &mut self,
+        data: &[u8],
+        symbols: &[Bitmap],
+    ) -> Result<(RegionInfo, Bitmap), PdfError> {
+        let mut bytes = Bytes { data };
+        let info = RegionInfo::read(&mut bytes)?;
+        let flags = bytes.u16()?;
+        if flags & 1 != 0 {
+            return Err(unsupported("Huffman coded text regions"));
+        }
+        if flags & 2 != 0 {
+            return Err(unsupported("refinement of text region symbols"));
+        }
+        let strips = 1i64 << (flags >> 2 & 3);
+        let corner = flags >> 4 & 3;
+        let transposed = flags & 0x40 != 0;
+        let operator = (flags >> 7 & 3) as u8;
+        // a 5-bit signed offset added between symbols
+        let spacing = ((flags >> 10 & 0x1F) as i8) << 3 >> 3;
+        // the region's area bounds the count, so that hostile data cannot
+        // have symbols placed for ever
+        let instances = bytes.u32()? as usize;
+        if instances > info.width.saturating_mul(info.height) {
+            return Err(PdfError::filter(
+                "JBIG2 text region places more symbols than it has pixels",
+            ));
+        }
+        let mut region = self.bitmap(info.width, info.height)?;
+        region.pixels.fill((flags >> 9 & 1) as u8);
+        let mut decoder = ArithmeticDecoder::new(bytes.data);
+        let mut strip_deltas = IntegerDecoder::new();
+        let mut first_deltas = IntegerDecoder::new();
+        let mut gaps = IntegerDecoder::new();
+        let mut offsets = IntegerDecoder::new();
+        let mut ids = IdDecoder::new(symbols.len().next_power_of_two().trailing_zeros());
+        let invalid = || PdfError::filter("Invalid JBIG2 text region");
+        let decode = |decoder: &mut IntegerDecoder, arithmetic: &mut ArithmeticDecoder| {
+            decoder.decode(arithmetic).ok_or_else(invalid)
+        };
+        // coordinates are clamped, as hostile data can push them far out
+        let clamp = |value: i64| value.clamp(-(1 << 40), 1 << 40);
+        let mut strip = clamp(-decode(&mut strip_deltas, &mut decoder)? * strips);
+        let mut first = 0i64;
+        let mut placed = 0;
+        while placed < instances {
+            decoder.check()?;
+            strip = clamp(strip + decode(&mut strip_deltas, &mut decoder)? * strips);
+            first = clamp(first + decode(&mut first_deltas, &mut decoder)?);
+            let mut s = first;
+            loop {
+                let offset = match strips {
+                    1 => 0,
+                    _ => decode(&mut offsets, &mut decoder)?,
+                };
+                decoder.check()?;
+                let t = strip + offset;
+                let symbol = symbols.get(ids.decode(&mut decoder)).ok_or_else(invalid)?;
+                let (width, height) = (symbol.width as i64, symbol.height as i64);
+                let (right, bottom) = (corner & 2 != 0, corner & 1 == 0);
+                // s advances along the strip by the symbol's extent, before
+                // or after placing it depending on the reference corner
+                let extent = if transposed { height } else { width } - 1;
+                let before = if transposed { bottom } else { right };
+                if before {
+                    s = clamp(s + extent);
+                }
+                let (x, y) = if transposed { (t, s) } else { (s, t) };
+                let x = x - if right { width - 1 } else { 0 };
+                let y = y - if bottom { height - 1 } else { 0 };
+                region.compose(symbol, x as isize, y as isize, operator);
+                if !before {
+                    s = clamp(s + extent);
+                }
+                placed += 1;
+                // a strip ends with an out-of-band gap; the count of
+                // instances stops data that never ends one
+                match gaps.decode(&mut decoder) {
+                    Some(gap) if placed < instances => s = clamp(s + gap + spacing as i64),
+                    _ => break,
+                }
+            }
+        }
+        Ok((info, region))
+    }
+}
@@ -0,0 +1,86 @@
+//! LZWDecode: the variable-width LZW compression of TIFF and PDF 1.0.
+use super::{int_parm, predictor, BitReader};
+use crate::error::PdfError;
+use crate::structure::Dictionary;
+
+const CLEAR_TABLE: usize = 256;
+const END_OF_DATA: usize = 257;
+const MAX_ENTRIES: usize = 4096;
+
+/// A table entry: the string of an earlier entry followed by one byte.
+#[derive(Clone, Copy)]
+struct Entry {
+    prefix: usize,
+    byte: u8,
+    first: u8,
+    len: usize,
+}
+
+/// Decodes to at most `limit` bytes. As one code can stand for thousands of
+/// bytes, data of a few kilobytes can otherwise decode to gigabytes.
+pub fn decode(data: &[u8], parms: Option<&Dictionary>, limit: usize) -> Result<Vec<u8>, PdfError> {
+    // with /EarlyChange 1, the default, codes widen one entry early
+    let early_change = match int_parm(parms, "EarlyChange", 1) {
+        0 => 0,
+        _ => 1,
+    };
+    let mut table: Vec<Entry> = (0..=END_OF_DATA)
+        .map(|code| Entry {
+            prefix: usize::MAX,
+            byte: code as u8,
+            first: code as u8,
+            len: 1,
+        })
+        .collect();
+    let mut bits = BitReader::new(data);
+    let mut output = vec![];
+    let mut width = 9;
+    let mut previous: Option<usize> = None;
+    // data which ends without an end-of-data code is taken as it is
+    while let Some(code) = bits.read(width) {
+        let code = code as usize;
+        if code == CLEAR_TABLE {
+            table.truncate(END_OF_DATA + 1);
+            width = 9;
+            previous = None;
+            continue;
+        }
+        if code == END_OF_DATA {
+            break;
+        }
+        if code > table.len() || (code == table.len() && previous.is_none()) {
+            return Err(PdfError::filter(format!("Invalid LZW code {}", code)));
+        }
+        if let Some(previous) = previous {
+            // a code not in the table yet is the previous string followed by
+            // its own first byte
+            let first = table.get(code).unwrap_or(&table[previous]).first;
+            if table.len() < MAX_ENTRIES {
+                table.push(Entry {
+                    prefix: previous,
+                    byte: first,
+                    first: table[previous].first,
+                    len: table[previous].len + 1,
+                });
+            }
+        }
+        let start = output.len();
+        if start + table[code].len > limit {
+            return Err(PdfError::filter("LZWDecode output is too large"));
+        }
+        output.resize(start + table[code].len, 0);
+        let mut entry = code;
+        for i in (start..output.len()).rev() {
+            output[i] = table[entry].byte;
+            entry = table[entry].prefix;
+        }
+        previous = Some(code);
+        width = match table.len() + early_change {
+            2048.. => 12,
+            1024.. => 11,
+            512.. => 10,
+            _ => 9,
+        };
+    }
+    predictor::unpredict(output, parms)
+}
@@ -0,0 +1,258 @@
+//! Stream filters, which undo the encodings named by a stream's /Filter.
+mod ascii;
+mod ccitt;
+mod dct;
+mod flate;
+mod jbig2;
+mod lzw;
+mod predictor;
+mod runlength;
+
+use crate::error::PdfError;
+use crate::structure::{AnyPDFData, Dictionary, Name};
+
+/// The most a single stream may decode to by default, so that a few
+/// kilobytes of compressed data cannot take all memory.
+pub const MAX_DECODED_LEN: usize = 1 << 30;
+
+/// One of the standard filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    AsciiHex,
+    Ascii85,
+    Lzw,
+    Flate,
+    RunLength,
+    CcittFax,
+    Jbig2,
+    Dct,
+    Jpx,
+    Crypt,
+}
+
+impl Filter {
+    /// Looks a filter up by name, including the abbreviations inline images
+    /// use.
+    pub fn from_name(name: &Name) -> Option<Filter> {
+        Some(match name.as_bytes() {
+            b"ASCIIHexDecode" | b"AHx" => Filter::AsciiHex,
+            b"ASCII85Decode" | b"A85" => Filter::Ascii85,
+            b"LZWDecode" | b"LZW" => Filter::Lzw,
+            b"FlateDecode" | b"Fl" => Filter::Flate,
+            b"RunLengthDecode" | b"RL" => Filter::RunLength,
+            b"CCITTFaxDecode" | b"CCF" => Filter::CcittFax,
+            b"JBIG2Decode" => Filter::Jbig2,
+            b"DCTDecode" | b"DCT" => Filter::Dct,
+            b"JPXDecode" => Filter::Jpx,
+            b"Crypt" => Filter::Crypt,
+            _ => return None,
+        })
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::AsciiHex => "ASCIIHexDecode",
+            Filter::Ascii85 => "ASCII85Decode",
+            Filter::Lzw => "LZWDecode",
+            Filter::Flate => "FlateDecode",
+            Filter::RunLength => "RunLengthDecode",
+            Filter::CcittFax => "CCITTFaxDecode",
+            Filter::Jbig2 => "JBIG2Decode",
+            Filter::Dct => "DCTDecode",
+            Filter::Jpx => "JPXDecode",
+            Filter::Crypt => "Crypt",
+        }
+    }
+    /// Decodes `data`, given this filter's entry of /DecodeParms.
+    pub fn decode(self, data: &[u8], parms: Option<&Dictionary>) -> Result<Vec<u8>, PdfError> {
+        self.decode_with(data, parms, DecodeOptions::default())
+    }
+    /// Decodes `data` like `decode`, to at most `options.max_decoded_len`
+    /// bytes. `options.decode_jpeg` is not consulted: DCTDecode always
+    /// decodes.
+    pub fn decode_with(
+        self,
+        data: &[u8],
+        parms: Option<&Dictionary>,
+        options: DecodeOptions,
+    ) -> Result<Vec<u8>, PdfError> {
+        let limit = options.max_decoded_len;
+        match self {
+            Filter::AsciiHex => ascii::decode_hex(data),
+            Filter::Ascii85 => ascii::decode_85(data),
+            Filter::Lzw => lzw::decode(data, parms, limit),
+            Filter::Flate => flate::decode(data, parms, limit),
+            Filter::RunLength => runlength::decode(data),
+            Filter::CcittFax => ccitt::decode(data, parms, limit),
+            Filter::Jbig2 => jbig2::decode(data, parms, limit),
+            Filter::Dct => dct::decode(data, parms, limit),
+            // only the identity crypt filter can be undone without the key
+            Filter::Crypt => match parms.and_then(|parms| parms.get_name("Name").ok()) {
+                Some(name) if name != "Identity" => Err(PdfError::encryption(format!(
+                    "Stream is encrypted with crypt filter {}",
+                    name
+                ))),
+                _ => Ok(data.to_vec()),
+            },
+            _ => Err(PdfError::filter(format!(
+                "Unsupported filter /{}",
+                self.name()
+            ))),
+        }
+    }
+}
+
+/// The filters a stream dictionary lists, in the order they are to be
+/// applied, each with its entry of /DecodeParms.
+pub fn filters(dict: &Dictionary) -> Result<Vec<(Filter, Option<&Dictionary>)>, PdfError> {
+    let names = match dict.get("Filter") {
+        None | Some(AnyPDFData::Null) => return Ok(vec![]),
+        Some(AnyPDFData::Name(name)) => vec![name],
+        Some(AnyPDFData::Array(names)) => names
+            .iter()
+            .map(|name| match name {
+                AnyPDFData::Name(name) => Ok(name),
+                other => Err(PdfError::syntax(format!(
+                    "Expected /Filter to hold names, found {}",
+                    other.type_name()
+                ))),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(other) => {
+            return Err(PdfError::syntax(format!(
+                "Expected /Filter to be a name or an array, found {}",
+                other.type_name()
+            )))
+        }
+    };
+    let parms = match dict.get("DecodeParms") {
+        Some(AnyPDFData::Array(parms)) => parms.iter().collect(),
+        Some(parms) => vec![parms],
+        None => vec![],
+    };
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let filter = Filter::from_name(name)
+                .ok_or_else(|| PdfError::filter(format!("Unknown filter {}", name)))?;
+            let parms = match parms.get(i) {
+                None | Some(AnyPDFData::Null) => None,
+                Some(AnyPDFData::Dictionary(parms)) => Some(parms),
+                Some(AnyPDFData::ObjRef(..)) => {
+                    return Err(PdfError::resolution(
+                        "/DecodeParms is a reference; decode the stream through its document",
+                    ))
+                }
+                Some(other) => {
+                    return Err(PdfError::syntax(format!(
+                        "Expected /DecodeParms to be a dictionary, found {}",
+                        other.type_name()
+                    )))
+                }
+            };
+            Ok((filter, parms))
+        })
+        .collect()
+}
+
+/// How far stream decoding goes.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeOptions {
+    /// Decode JPEG images to raw samples. Without it, decoding stops at a
+    /// DCTDecode filter and gives the JPEG data untouched.
+    pub decode_jpeg: bool,
+    /// The most any one filter may decode to; more is an error. Defaults to
+    /// `MAX_DECODED_LEN`.
+    pub max_decoded_len: usize,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            decode_jpeg: false,
+            max_decoded_len: MAX_DECODED_LEN,
+        }
+    }
+}
+
+/// Applies every filter of a stream dictionary to its data, passing JPEG
+/// images through.
+pub fn decode(dict: &Dictionary, data: &[u8]) -> Result<Vec<u8>, PdfError> {
+    decode_with(dict, data, DecodeOptions::default())
+}
+
+pub fn decode_with(
+    dict: &Dictionary,
+    data: &[u8],
+    options: DecodeOptions,
+) -> Result<Vec<u8>, PdfError> {
+    let mut data = data.to_vec();
+    for (filter, parms) in filters(dict)? {
+        if filter == Filter::Dct && !options.decode_jpeg {
+            break;
+        }
+        data = filter.decode_with(&data, parms, options)?;
+    }
+    Ok(data)
+}
+
+/// Reads an integer from /DecodeParms, which may be absent.
+fn int_parm(parms: Option<&Dictionary>, key: &str, default: i64) -> i64 {
+    parms
+        .and_then(|parms| parms.get_int(key).ok())
+        .unwrap_or(default)
+}
+
+/// Reads a boolean from /DecodeParms, which may be absent.
+fn bool_parm(parms: Option<&Dictionary>, key: &str, default: bool) -> bool {
+    match parms.and_then(|parms| parms.get(key)) {
+        Some(AnyPDFData::Boolean(value)) => *value,
+        _ => default,
+    }
+}
+
+/// Reads numbers of up to 32 bits from a byte slice, most significant bit
+/// first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// In bits.
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, position: 0 }
+    }
+    /// Reads `count` bits, or `None` if fewer are left.
+    fn read(&mut self, count: usize) -> Option<u32> {
+        if self.remaining() < count {
+            return None;
+        }
+        let value = self.peek(count);
+        self.position += count;
+        Some(value)
+    }
+    /// The next `count` bits, as if the data were followed by zeros.
+    fn peek(&self, count: usize) -> u32 {
+        (self.position..self.position + count).fold(0, |value, position| {
+            let byte = self.data.get(position / 8).copied().unwrap_or(0);
+            value << 1 | (byte >> (7 - position % 8) & 1) as u32
+        })
+    }
+    fn skip(&mut self, count: usize) {
+        self.position = (self.position + count).min(self.data.len() * 8);
+    }
+    /// Skips to the start of the next byte, unless already there.
+    fn align(&mut self) {
+        self.skip(self.position.wrapping_neg() % 8);
+    }
+    /// How many 0 bits come next.
+    fn leading_zeros(&self) -> usize {
+        (self.position..self.data.len() * 8)
+            .find(|&position| self.data[position / 8] >> (7 - position % 8) & 1 == 1)
+            .map_or(self.remaining(), |position| position - self.position)
+    }
+    fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.position
+    }
+}
@@ -0,0 +1,133 @@
+//! The predictors LZWDecode and FlateDecode can apply before compressing.
+use super::int_parm;
+use crate::error::PdfError;
+use crate::structure::Dictionary;
+
+/// Undoes the predictor named by /Predictor, if any.
+pub fn unpredict(data: Vec<u8>, parms: Option<&Dictionary>) -> Result<Vec<u8>, PdfError> {
+    let predictor = int_parm(parms, "Predictor", 1);
+    if predictor == 1 {
+        return Ok(data);
+    }
+    let parm = |key, default| usize::try_from(int_parm(parms, key, default)).ok();
+    let (Some(colors), Some(bits_per_component), Some(columns)) = (
+        parm("Colors", 1),
+        parm("BitsPerComponent", 8),
+        parm("Columns", 1),
+    ) else {
+        return Err(PdfError::filter("Invalid predictor parameters"));
+    };
+    if !matches!(bits_per_component, 1 | 2 | 4 | 8 | 16) {
+        return Err(PdfError::filter(format!(
+            "Invalid predictor /BitsPerComponent {}",
+            bits_per_component
+        )));
+    }
+    let pixel_bits = colors.checked_mul(bits_per_component);
+    let row_bits = pixel_bits.and_then(|bits| bits.checked_mul(columns));
+    let (Some(pixel_bits), Some(row_bits)) = (pixel_bits, row_bits) else {
+        return Err(PdfError::filter("Predictor rows are too long"));
+    };
+    let row_len = row_bits.div_ceil(8);
+    match predictor {
+        2 => Ok(tiff_unpredict(data, colors, bits_per_component, columns)),
+        10..=15 => png_unpredict(&data, pixel_bits.div_ceil(8), row_len),
+        _ => Err(PdfError::filter(format!(
+            "Unsupported predictor {}",
+            predictor
+        ))),
+    }
+}
+
+/// Reverses the TIFF predictor (/Predictor 2), where every component but
+/// those of the first pixel in a row is stored as the difference from the
+/// same component of the pixel to its left.
+fn tiff_unpredict(
+    mut data: Vec<u8>,
+    colors: usize,
+    bits_per_component: usize,
+    columns: usize,
+) -> Vec<u8> {
+    let row_len = (colors * columns * bits_per_component).div_ceil(8);
+    if row_len == 0 {
+        return data;
+    }
+    for row in data.chunks_mut(row_len) {
+        match bits_per_component {
+            8 => {
+                for i in colors..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - colors]);
+                }
+            }
+            16 => {
+                for i in (colors * 2..row.len().saturating_sub(1)).step_by(2) {
+                    let left = u16::from_be_bytes([row[i - colors * 2], row[i - colors * 2 + 1]]);
+                    let value = u16::from_be_bytes([row[i], row[i + 1]]).wrapping_add(left);
+                    row[i..i + 2].copy_from_slice(&value.to_be_bytes());
+                }
+            }
+            bits => {
+                // components smaller than a byte, packed from the high bit
+                let mask = (1u8 << bits) - 1;
+                let count = (colors * columns).min(row.len() * 8 / bits);
+                let get =
+                    |row: &[u8], n: usize| (row[n * bits / 8] >> (8 - bits - n * bits % 8)) & mask;
+                for n in colors..count {
+                    let value = get(row, n).wrapping_add(get(row, n - colors)) & mask;
+                    let shift = 8 - bits - n * bits % 8;
+                    let byte = &mut row[n * bits / 8];
+                    *byte = (*byte & !(mask << shift)) | (value << shift);
+                }
+            }
+        }
+    }
+    data
+}
+
+/// Reverses the PNG predictors (/Predictor 10 and above), where every row is
+/// prefixed by a byte naming the filter used for that row. The value of
+/// /Predictor itself only says which filter the encoder preferred.
+fn png_unpredict(data: &[u8], pixel_len: usize, row_len: usize) -> Result<Vec<u8>, PdfError> {
+    let bpp = pixel_len.max(1);
+    if row_len > data.len() {
+        return Err(PdfError::filter("Predictor rows are longer than the data"));
+    }
+    let mut output = Vec::with_capacity(data.len());
+    let mut previous = vec![0u8; row_len];
+    for chunk in data.chunks(row_len + 1) {
+        let filter = chunk[0];
+        let mut row = chunk[1..].to_vec();
+        row.resize(row_len, 0);
+        for i in 0..row_len {
+            let left = if i >= bpp { row[i - bpp] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => {
+                    let p = left as i16 + up as i16 - up_left as i16;
+                    let (pa, pb, pc) = (
+                        (p - left as i16).abs(),
+                        (p - up as i16).abs(),
+                        (p - up_left as i16).abs(),
+                    );
+                    if pa <= pb && pa <= pc {
+                        left
+                    } else if pb <= pc {
+                        up
+                    } else {
+                        up_left
+                    }
+                }
+                _ => return Err(PdfError::filter("Invalid PNG predictor")),
+            };
+            row[i] = row[i].wrapping_add(predicted);
+        }
+        output.extend_from_slice(&row);
+        previous = row;
+    }
+    Ok(output)
+}
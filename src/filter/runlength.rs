@@ -0,0 +1,31 @@
+//! RunLengthDecode: a byte-oriented run-length encoding.
+use crate::error::PdfError;
+
+/// Each run starts with a length byte: 0 to 127 copy the next 1 to 128
+/// bytes, 129 to 255 repeat the next byte 2 to 128 times, and 128 marks the
+/// end of the data.
+pub fn decode(data: &[u8]) -> Result<Vec<u8>, PdfError> {
+    let mut output = Vec::with_capacity(data.len() * 2);
+    let mut rest = data;
+    while let Some((&length, tail)) = rest.split_first() {
+        match length {
+            0..=127 => {
+                let count = length as usize + 1;
+                if tail.len() < count {
+                    return Err(PdfError::filter("RunLengthDecode data is truncated"));
+                }
+                output.extend_from_slice(&tail[..count]);
+                rest = &tail[count..];
+            }
+            128 => break,
+            _ => {
+                let Some((&byte, tail)) = tail.split_first() else {
+                    return Err(PdfError::filter("RunLengthDecode data is truncated"));
+                };
+                output.resize(output.len() + 257 - length as usize, byte);
+                rest = tail;
+            }
+        }
+    }
+    Ok(output)
+}
@@ -0,0 +1,328 @@
+use crate::error::PdfError;
+use crate::reader::StreamReader;
+use std::collections::VecDeque;
+
+/// PDF whitespace: NUL, tab, line feed, form feed, carriage return and space.
+pub fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b'\0' | b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+pub fn is_delimiter(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+pub fn is_regular(byte: u8) -> bool {
+    !is_whitespace(byte) && !is_delimiter(byte)
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Integer(i64),
+    Real(f64),
+    /// A name with its `#xx` escapes decoded, without the leading slash.
+    Name(Vec<u8>),
+    /// A `(literal)` string with its escapes and line endings decoded.
+    LiteralString(Vec<u8>),
+    /// A `<hex>` string, decoded to the bytes it stands for.
+    HexString(Vec<u8>),
+    /// Any other run of regular characters, e.g. `obj`, `R` or `true`.
+    Keyword(Vec<u8>),
+    ArrayStart,
+    ArrayEnd,
+    DictStart,
+    DictEnd,
+    ProcStart,
+    ProcEnd,
+}
+
+impl TokenKind {
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, TokenKind::Keyword(bytes) if bytes == keyword.as_bytes())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Where the token starts in the file.
+    pub offset: usize,
+}
+
+/// Splits the bytes of a reader into tokens, skipping whitespace and
+/// comments. Tokens can be looked at ahead of time without consuming them;
+/// when the lexer is dropped, the reader is left at the first token which
+/// was not consumed.
+pub struct Lexer<'r, R: StreamReader> {
+    reader: &'r mut R,
+    lookahead: VecDeque<Token>,
+    lenient: bool,
+}
+
+impl<R: StreamReader> Drop for Lexer<'_, R> {
+    fn drop(&mut self) {
+        self.rewind();
+    }
+}
+
+impl<'r, R: StreamReader> Lexer<'r, R> {
+    pub fn new(reader: &'r mut R) -> Self {
+        Lexer {
+            reader,
+            lookahead: VecDeque::new(),
+            lenient: false,
+        }
+    }
+    /// Keeps malformed `#` escapes in names as they are instead of failing.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+    pub fn seek(&mut self, offset: usize) {
+        self.lookahead.clear();
+        self.reader.seek(offset);
+    }
+    fn rewind(&mut self) {
+        if let Some(token) = self.lookahead.front() {
+            let offset = token.offset;
+            self.lookahead.clear();
+            self.reader.seek(offset);
+        }
+    }
+    /// Gives access to the underlying reader, positioned right after the last
+    /// consumed token. Used for data which is not made of tokens, like the
+    /// contents of a stream.
+    pub fn raw(&mut self) -> &mut R {
+        self.rewind();
+        self.reader
+    }
+    pub fn next_token(&mut self) -> Result<Option<Token>, PdfError> {
+        match self.lookahead.pop_front() {
+            Some(token) => Ok(Some(token)),
+            None => self.lex(),
+        }
+    }
+    /// Looks at the `n`th upcoming token (starting from 0) without consuming
+    /// it.
+    pub fn peek_nth(&mut self, n: usize) -> Result<Option<&Token>, PdfError> {
+        while self.lookahead.len() <= n {
+            match self.lex()? {
+                Some(token) => self.lookahead.push_back(token),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.lookahead.get(n))
+    }
+    pub fn peek(&mut self) -> Result<Option<&Token>, PdfError> {
+        self.peek_nth(0)
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        while !self.reader.at_eof() {
+            let byte = self.reader.peek_next();
+            if is_whitespace(byte) {
+                self.reader.advance(1);
+            } else if byte == b'%' {
+                while !self.reader.at_eof() && !matches!(self.reader.peek_next(), b'\r' | b'\n') {
+                    self.reader.advance(1);
+                }
+            } else {
+                break;
+            }
+        }
+    }
+    /// Lexes the next token from the reader. On failure the reader is put
+    /// back at the start of the token.
+    fn lex(&mut self) -> Result<Option<Token>, PdfError> {
+        self.skip_whitespace_and_comments();
+        if self.reader.at_eof() {
+            return Ok(None);
+        }
+        let offset = self.reader.position();
+        let kind = self.lex_kind();
+        if kind.is_err() {
+            self.reader.seek(offset);
+        }
+        Ok(Some(Token {
+            kind: kind?,
+            offset,
+        }))
+    }
+    fn lex_kind(&mut self) -> Result<TokenKind, PdfError> {
+        let reader = &mut *self.reader;
+        match reader.next() {
+            b'[' => Ok(TokenKind::ArrayStart),
+            b']' => Ok(TokenKind::ArrayEnd),
+            b'{' => Ok(TokenKind::ProcStart),
+            b'}' => Ok(TokenKind::ProcEnd),
+            b'<' if reader.peek(1) == b"<" => {
+                reader.advance(1);
+                Ok(TokenKind::DictStart)
+            }
+            b'>' if reader.peek(1) == b">" => {
+                reader.advance(1);
+                Ok(TokenKind::DictEnd)
+            }
+            b'<' => self.lex_hex_string(),
+            b'(' => self.lex_literal_string(),
+            b'/' => Ok(TokenKind::Name(self.lex_name()?)),
+            b')' | b'>' => Err(PdfError::lex("Unexpected closing delimiter")),
+            first => {
+                let mut bytes = vec![first];
+                while !reader.at_eof() && is_regular(reader.peek_next()) {
+                    bytes.push(reader.next());
+                }
+                Ok(Self::number_or_keyword(bytes))
+            }
+        }
+    }
+    fn number_or_keyword(bytes: Vec<u8>) -> TokenKind {
+        let is_number =
+            bytes.iter().enumerate().all(|(i, &x)| {
+                x.is_ascii_digit() || x == b'.' || (i == 0 && (x == b'+' || x == b'-'))
+            }) && bytes.iter().any(u8::is_ascii_digit)
+                && bytes.iter().filter(|&&x| x == b'.').count() <= 1;
+        if !is_number {
+            return TokenKind::Keyword(bytes);
+        }
+        // only ASCII digits, signs and dots are left, so this is valid UTF-8
+        let text = String::from_utf8_lossy(&bytes);
+        if !bytes.contains(&b'.') {
+            if let Ok(int) = text.parse::<i64>() {
+                return TokenKind::Integer(int);
+            }
+        }
+        match text.parse::<f64>() {
+            Ok(real) => TokenKind::Real(real),
+            Err(_) => TokenKind::Keyword(bytes),
+        }
+    }
+    fn lex_literal_string(&mut self) -> Result<TokenKind, PdfError> {
+        let reader = &mut *self.reader;
+        let mut depth = 1;
+        let mut string = vec![];
+        loop {
+            if reader.at_eof() {
+                return Err(PdfError::truncated("Unterminated string"));
+            }
+            match reader.next() {
+                b'\\' => {
+                    let escaped = reader.next();
+                    match escaped {
+                        b'n' => string.push(b'\n'),
+                        b'r' => string.push(b'\r'),
+                        b't' => string.push(b'\t'),
+                        b'b' => string.push(0x08),
+                        b'f' => string.push(0x0C),
+                        b'0'..=b'7' => {
+                            // up to three octal digits; overflow is ignored
+                            let mut value = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match reader.peek_next() {
+                                    digit @ b'0'..=b'7' if !reader.at_eof() => {
+                                        value = value * 8 + (digit - b'0') as u32;
+                                        reader.advance(1);
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            string.push(value as u8);
+                        }
+                        // a backslash at the end of a line continues the string
+                        b'\r' => {
+                            if reader.peek_next() == b'\n' {
+                                reader.advance(1);
+                            }
+                        }
+                        b'\n' => {}
+                        // this covers \( \) and \\ too; other backslashes are ignored
+                        other => string.push(other),
+                    }
+                }
+                b'\r' => {
+                    if reader.peek_next() == b'\n' {
+                        reader.advance(1);
+                    }
+                    string.push(b'\n');
+                }
+                b'(' => {
+                    depth += 1;
+                    string.push(b'(');
+                }
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(TokenKind::LiteralString(string));
+                    }
+                    string.push(b')');
+                }
+                byte => string.push(byte),
+            }
+        }
+    }
+    fn lex_hex_string(&mut self) -> Result<TokenKind, PdfError> {
+        let reader = &mut *self.reader;
+        let mut digits = vec![];
+        loop {
+            if reader.at_eof() {
+                return Err(PdfError::truncated("Unterminated hex string"));
+            }
+            match reader.next() {
+                b'>' => break,
+                byte if is_whitespace(byte) => {}
+                byte => match hex_value(byte) {
+                    Some(value) => digits.push(value),
+                    None => return Err(PdfError::lex("Invalid character in hex string")),
+                },
+            }
+        }
+        // an odd final digit is treated as if followed by 0
+        Ok(TokenKind::HexString(
+            digits
+                .chunks(2)
+                .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+                .collect(),
+        ))
+    }
+    /// Reads a name, decoding `#xx` escapes. A `#` not followed by two hex
+    /// digits, or one escaping a NUL byte, is malformed.
+    fn lex_name(&mut self) -> Result<Vec<u8>, PdfError> {
+        let reader = &mut *self.reader;
+        let mut name = vec![];
+        while !reader.at_eof() && is_regular(reader.peek_next()) {
+            let position = reader.position();
+            let byte = reader.next();
+            if byte != b'#' {
+                name.push(byte);
+                continue;
+            }
+            let escape = reader.peek(2);
+            let value = match (escape.first(), escape.get(1)) {
+                (Some(&high), Some(&low)) => hex_value(high).zip(hex_value(low)),
+                _ => None,
+            };
+            match value {
+                Some((high, low)) if high | low != 0 => {
+                    name.push(high << 4 | low);
+                    reader.advance(2);
+                }
+                _ if self.lenient => name.push(b'#'),
+                _ => {
+                    return Err(PdfError::lex("Malformed # escape in name").at(reader, position));
+                }
+            }
+        }
+        Ok(name)
+    }
+}
@@ -0,0 +1,15 @@
+//! A PDF parser. Open files with [`Document`]; the modules below expose the
+//! lower-level pieces it is built from.
+pub mod document;
+pub mod encoding;
+pub mod error;
+pub mod filter;
+pub mod lexer;
+pub mod parser;
+pub mod reader;
+pub mod structure;
+#[cfg(test)]
+mod test;
+
+pub use document::Document;
+pub use error::PdfError;
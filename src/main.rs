@@ -1,25 +1,75 @@
-#![feature(type_alias_impl_trait)]
-#![feature(bufreader_peek)]
-mod reader;
-use crate::reader::*;
-// imports
-mod parser;
-mod structure;
-#[cfg(test)]
-mod test;
-use parser::parse;
-use std::io;
+use pdf::parser::ParseOptions;
+use pdf::reader::{PDFReader, SeekReader, StreamReader};
+use pdf::structure::{AnyPDFData, Object};
+use pdf::{Document, PdfError};
+use std::fs::File;
+use std::process::ExitCode;
 
-const BLOCK: bool = true;
-fn main() -> io::Result<()> {
-    // let test = PDFTEST.to_owned();
-    let test = std::fs::File::open("src/test.pdf").unwrap();
-    let mut reader = PDFReader::from(test);
-    let pdf = parse(&mut reader);
-    // dbg!(pdf);
-    if BLOCK {
-        Err(io::Error::new(io::ErrorKind::Other, "Blocking"))
+const USAGE: &str = "usage: pdf [--strict] [--seek | --mmap] <file.pdf>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let flag = |name: &str| args.iter().any(|x| x == name);
+    let Some(path) = args.iter().find(|x| !x.starts_with("--")) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let options = if flag("--strict") {
+        ParseOptions::strict()
+    } else {
+        ParseOptions::default()
+    };
+    let result = if flag("--seek") {
+        File::open(path)
+            .and_then(SeekReader::new)
+            .map_err(PdfError::from)
+            .and_then(|reader| report(Document::load(reader, options)?))
+    } else if flag("--mmap") {
+        PDFReader::map(path)
+            .map_err(PdfError::from)
+            .and_then(|reader| report(Document::load(reader, options)?))
     } else {
-        Ok(())
+        PDFReader::open(path)
+            .map_err(PdfError::from)
+            .and_then(|reader| report(Document::load(reader, options)?))
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn report<R: StreamReader>(mut document: Document<R>) -> Result<(), PdfError> {
+    println!("{} ({} objects)", document.version(), document.xref().len());
+    for key in ["Size", "Root", "Info", "ID"] {
+        println!("{}: {:?}", key, document.trailer().get(key));
+    }
+    if let Some(reason) = document.recovered() {
+        println!("Rebuilt xref ({})", reason);
+    }
+    for revision in document.revisions() {
+        println!(
+            "Revision {:?}: {} objects changed",
+            revision.range,
+            revision.changed.len()
+        );
+    }
+    if let Ok((number, gen)) = document.trailer().get_ref("Info") {
+        if let Some(Object {
+            data: AnyPDFData::Dictionary(info),
+            ..
+        }) = document.get_reference(number, gen)?
+        {
+            for (key, value) in info.iter() {
+                if let AnyPDFData::String(string) = value {
+                    println!("{}: {}", key, string.to_text());
+                }
+            }
+        }
     }
+    println!("Catalog: {:?}", document.catalog()?);
+    Ok(())
 }
@@ -1,325 +1,892 @@
+use crate::error::PdfError;
+use crate::lexer::{Lexer, Token, TokenKind};
 use crate::reader::{u8s_to_string, PDFReader, StreamReader};
-use crate::structure::{AnyPDFData, Object, PDF};
-use flate2::read::ZlibDecoder;
-use regex::Regex;
-use std::io;
-use std::io::prelude::*;
+use crate::structure::{
+    AnyPDFData, Dictionary, Name, Object, PdfString, Revision, Stream, XrefEntry, PDF,
+};
+use regex::bytes::Regex as BytesRegex;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// How deeply arrays and dictionaries may be nested, to keep hostile files
+/// from overflowing the stack.
+const MAX_DEPTH: usize = 64;
+
+fn unexpected<R: StreamReader>(
+    lexer: &mut Lexer<R>,
+    token: Option<Token>,
+    expected: &str,
+) -> PdfError {
+    let reader = lexer.raw();
+    match token {
+        Some(token) => PdfError::syntax(format!("Expected {}", expected)).at(reader, token.offset),
+        None => {
+            let end = reader.len();
+            PdfError::truncated(format!(
+                "Expected {} but reached the end of the file",
+                expected
+            ))
+            .at(reader, end)
+        }
+    }
+}
 
 impl AnyPDFData {
-    fn consume_bool(reader: &mut PDFReader) -> Result<AnyPDFData, io::Error> {
-        let v = match reader {
-            t if t.peek(4) == "true".as_bytes() => {
-                t.advance(4);
-                true
-            }
-            t if t.peek(5) == "false".as_bytes() => {
-                t.advance(5);
-                false
+    fn consume_array<R: StreamReader>(
+        lexer: &mut Lexer<R>,
+        depth: usize,
+    ) -> Result<AnyPDFData, PdfError> {
+        let mut objects: Vec<AnyPDFData> = vec![];
+        loop {
+            match lexer.peek()? {
+                Some(Token {
+                    kind: TokenKind::ArrayEnd,
+                    ..
+                }) => {
+                    lexer.next_token()?;
+                    return Ok(Self::Array(objects));
+                }
+                Some(_) => objects.push(Self::parse_nested(lexer, depth)?),
+                None => return Err(unexpected(lexer, None, "]")),
             }
-            &mut _ => unimplemented!(),
-        };
-        Ok(AnyPDFData::Boolean(v))
+        }
     }
-    fn consume_num(reader: &mut PDFReader) -> Result<AnyPDFData, io::Error> {
-        let mut num = String::new();
+    fn consume_dict<R: StreamReader>(
+        lexer: &mut Lexer<R>,
+        depth: usize,
+    ) -> Result<Dictionary, PdfError> {
+        let mut dict = Dictionary::new();
         loop {
-            let token: char = reader.peek_next().into();
-            if "-.0123456789+".contains(token) {
-                num.push(token);
-                reader.advance(1);
-            } else {
-                break;
-            }
+            let name = match lexer.next_token()? {
+                Some(Token {
+                    kind: TokenKind::DictEnd,
+                    ..
+                }) => return Ok(dict),
+                Some(Token {
+                    kind: TokenKind::Name(name),
+                    ..
+                }) => name,
+                token => return Err(unexpected(lexer, token, "a name as dictionary key")),
+            };
+            let value = Self::parse_nested(lexer, depth)?;
+            dict.insert(name, value);
         }
-        let i = num.parse::<i64>();
-        let f = num.parse::<f64>();
-        match (i, f) {
-            (Ok(int), Ok(float)) => {
-                if int as f64 == float {
-                    Ok(Self::Integer(int))
-                } else {
-                    Ok(Self::Real(float))
-                }
+    }
+    /// Reads the data of a stream, with the reader placed right after the
+    /// `stream` keyword.
+    fn consume_stream<R: StreamReader>(
+        reader: &mut R,
+        dict: Dictionary,
+    ) -> Result<AnyPDFData, PdfError> {
+        let length = dict
+            .get_int("Length")
+            .ok()
+            .and_then(|length| usize::try_from(length).ok());
+        if reader.peek_next() == b'\r' {
+            reader.advance(1);
+        }
+        if reader.peek_next() == b'\n' {
+            reader.advance(1);
+        }
+        let start = reader.position();
+        if let Some(length) = length {
+            reader.seek(start.saturating_add(length));
+            reader.skip_whitespace();
+            if reader.peek(9) == b"endstream" {
+                reader.seek(start);
+                let stream: Vec<u8> = reader.read(length).into();
+                reader.skip_whitespace();
+                reader.advance(9);
+                return Ok(AnyPDFData::Stream(Stream { dict, data: stream }));
             }
-            (Ok(int), _) => Ok(Self::Integer(int)),
-            (_, Ok(float)) => Ok(Self::Real(float)),
-            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid number")),
         }
+        // the length is indirect or wrong, so look for the end of the data
+        reader.seek(start);
+        let Some(end) = reader.find(b"endstream") else {
+            return Err(PdfError::truncated("Stream is missing endstream").at(reader, start));
+        };
+        let mut stream: Vec<u8> = reader.read(end - start).into();
+        if stream.ends_with(b"\n") {
+            stream.pop();
+        }
+        if stream.ends_with(b"\r") {
+            stream.pop();
+        }
+        reader.advance(9);
+        Ok(AnyPDFData::Stream(Stream { dict, data: stream }))
     }
-    fn consume_lit_str(reader: &mut PDFReader) -> Result<AnyPDFData, io::Error> {
-        reader.advance(1);
-        let mut level = 1;
-        let mut string = String::new();
-        loop {
-            let mut token: char = reader.next().into();
-            if token == '\\' {
-                let next: char = reader.peek_next().into();
-                // to add, to advance
-                let push = match next {
-                    'n' => ("\n", 1),
-                    'r' => ("\r", 1),
-                    'b' => ("\x7F", 1),
-                    'f' => ("\x0C", 1),
-                    '(' => ("(", 1),
-                    ')' => (")", 1),
-                    '\\' => ("\\", 1),
-                    '\n' => ("", 1),
-                    '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' => {
-                        let octal = u8::from_str_radix(u8s_to_string(reader.read(3))?, 8).unwrap();
-                        static mut OCTAL_STR: String = String::new();
-                        unsafe {
-                            OCTAL_STR = char::from(octal).to_string();
-                            #[allow(static_mut_refs)]
-                            (OCTAL_STR.as_str(), 0)
-                        }
-                    }
-                    _ => ("", 0),
+    fn parse<R: StreamReader>(lexer: &mut Lexer<R>) -> Result<AnyPDFData, PdfError> {
+        Self::parse_nested(lexer, 0)
+    }
+    /// Parses the object starting at the next token, `depth` arrays or
+    /// dictionaries deep. An integer followed by another integer and `R` is
+    /// an indirect reference.
+    fn parse_nested<R: StreamReader>(
+        lexer: &mut Lexer<R>,
+        depth: usize,
+    ) -> Result<AnyPDFData, PdfError> {
+        let Some(token) = lexer.next_token()? else {
+            return Err(unexpected(lexer, None, "an object"));
+        };
+        match token.kind {
+            TokenKind::Integer(number) => {
+                let gen = match lexer.peek_nth(0) {
+                    Ok(Some(Token {
+                        kind: TokenKind::Integer(gen),
+                        ..
+                    })) => *gen,
+                    _ => return Ok(Self::Integer(number)),
                 };
-                reader.advance(push.1);
-                string += push.0;
-            } else {
-                if token as u8 == 0x0D {
-                    token = 0x0A as char;
-                } else if token == '(' {
-                    level += 1;
-                } else if token == ')' {
-                    level -= 1;
-                    if level == 0 {
-                        break;
+                if !matches!(lexer.peek_nth(1), Ok(Some(token)) if token.kind.is_keyword("R")) {
+                    return Ok(Self::Integer(number));
+                }
+                lexer.next_token()?;
+                lexer.next_token()?;
+                Ok(Self::ObjRef(number, gen))
+            }
+            TokenKind::Real(real) => Ok(Self::Real(real)),
+            TokenKind::Name(name) => Ok(Self::Name(name.into())),
+            TokenKind::LiteralString(string) => Ok(Self::String(PdfString::literal(string))),
+            TokenKind::HexString(string) => Ok(Self::String(PdfString::hex(string))),
+            TokenKind::ArrayStart | TokenKind::DictStart if depth >= MAX_DEPTH => {
+                Err(PdfError::syntax("Objects are nested too deeply").at(lexer.raw(), token.offset))
+            }
+            TokenKind::ArrayStart => Self::consume_array(lexer, depth + 1),
+            TokenKind::DictStart => {
+                let dict = Self::consume_dict(lexer, depth + 1)?;
+                match lexer.peek()? {
+                    Some(token) if token.kind.is_keyword("stream") => {
+                        lexer.next_token()?;
+                        Self::consume_stream(lexer.raw(), dict)
                     }
+                    _ => Ok(Self::Dictionary(dict)),
                 }
-                string.push(token);
             }
+            ref keyword if keyword.is_keyword("true") => Ok(Self::Boolean(true)),
+            ref keyword if keyword.is_keyword("false") => Ok(Self::Boolean(false)),
+            ref keyword if keyword.is_keyword("null") => Ok(Self::Null),
+            _ => Err(PdfError::syntax("Unable to parse object").at(lexer.raw(), token.offset)),
         }
-        return Ok(Self::String(string));
     }
-    fn consume_hex_str(reader: &mut PDFReader) -> AnyPDFData {
-        reader.advance(1);
-        let mut string = String::new();
-        loop {
-            let token1: char = reader.next().into();
-            let mut token2: char = reader.peek_next().into();
-            if token1 == '>' {
-                break;
+    /// Parses one object at the reader's position. Never panics, whatever
+    /// the input.
+    pub fn consume<R: StreamReader>(reader: &mut R) -> Result<AnyPDFData, PdfError> {
+        Self::parse(&mut Lexer::new(reader))
+    }
+}
+
+impl Object {
+    fn consume<R: StreamReader>(reader: &mut R) -> Result<Object, PdfError> {
+        Self::consume_with(reader, false)
+    }
+    /// Parses `N G obj ... endobj`. Unless `lenient`, an object which is not
+    /// followed by `endobj`, or a name with a malformed `#` escape, is an
+    /// error; either way, running out of file in the middle of the object is
+    /// one.
+    fn consume_with<R: StreamReader>(reader: &mut R, lenient: bool) -> Result<Object, PdfError> {
+        let mut lexer = Lexer::new(reader).lenient(lenient);
+        let number = expect_int(&mut lexer)?;
+        let gen = expect_int(&mut lexer)?;
+        expect_keyword(&mut lexer, "obj")?;
+        let data = AnyPDFData::parse(&mut lexer).map_err(|e| e.in_object(number, gen))?;
+        let object = Object { number, gen, data };
+        let offset = match lexer.peek() {
+            Ok(Some(token)) if token.kind.is_keyword("endobj") => {
+                lexer.next_token()?;
+                return Ok(object);
             }
-            if token2 == '>' {
-                token2 = '0';
-            } else {
-                reader.advance(1);
+            _ if lenient => return Ok(object),
+            Ok(None) => {
+                let reader = lexer.raw();
+                let end = reader.len();
+                return Err(PdfError::truncated("Object is truncated")
+                    .at(reader, end)
+                    .in_object(number, gen));
             }
-            string.push(
-                char::from_u32(u32::from_str_radix(&format!("{}{}", token1, token2), 16).unwrap())
-                    .unwrap(),
-            );
+            Ok(Some(token)) => token.offset,
+            Err(_) => lexer.raw().position(),
+        };
+        let next_object = matches!(
+            lexer.peek_nth(0),
+            Ok(Some(Token {
+                kind: TokenKind::Integer(_),
+                ..
+            }))
+        ) && matches!(
+            lexer.peek_nth(1),
+            Ok(Some(Token {
+                kind: TokenKind::Integer(_),
+                ..
+            }))
+        ) && matches!(lexer.peek_nth(2), Ok(Some(token)) if token.kind.is_keyword("obj"));
+        let end_of_body = matches!(
+            lexer.peek(),
+            Ok(Some(token)) if ["xref", "trailer", "startxref"].iter().any(|x| token.kind.is_keyword(x))
+        );
+        let message = if next_object || end_of_body {
+            "Missing endobj"
+        } else {
+            "Extra tokens before endobj"
+        };
+        Err(PdfError::syntax(message)
+            .at(lexer.raw(), offset)
+            .in_object(number, gen))
+    }
+}
+
+fn expect_int<R: StreamReader>(lexer: &mut Lexer<R>) -> Result<i64, PdfError> {
+    match lexer.next_token()? {
+        Some(Token {
+            kind: TokenKind::Integer(int),
+            ..
+        }) => Ok(int),
+        token => Err(unexpected(lexer, token, "an integer")),
+    }
+}
+
+fn expect_keyword<R: StreamReader>(lexer: &mut Lexer<R>, keyword: &str) -> Result<(), PdfError> {
+    match lexer.next_token()? {
+        Some(token) if token.kind.is_keyword(keyword) => Ok(()),
+        token => Err(unexpected(lexer, token, &format!("keyword {}", keyword))),
+    }
+}
+
+/// Reads the byte offset following the last `startxref` keyword in the file.
+fn find_startxref<R: StreamReader>(reader: &mut R) -> Option<usize> {
+    let position = reader.rfind(b"startxref")?;
+    reader.seek(position + 9);
+    usize::try_from(expect_int(&mut Lexer::new(reader)).ok()?).ok()
+}
+
+/// Parses one `xref` table and the `trailer` dictionary that follows it.
+fn consume_xref_table<R: StreamReader>(
+    reader: &mut R,
+) -> Result<(Vec<(i64, XrefEntry)>, Dictionary), PdfError> {
+    let mut lexer = Lexer::new(reader);
+    expect_keyword(&mut lexer, "xref")?;
+    let mut entries = vec![];
+    loop {
+        if matches!(lexer.peek()?, Some(token) if token.kind.is_keyword("trailer")) {
+            break;
         }
-        return Self::String(string);
-    }
-    fn consume_name(reader: &mut PDFReader) -> Result<AnyPDFData, io::Error> {
-        reader.advance(1);
-        let mut name = String::new();
-        let break_char = vec![
-            u8::from_str_radix("00", 16).unwrap() as char,
-            u8::from_str_radix("09", 16).unwrap() as char,
-            u8::from_str_radix("0A", 16).unwrap() as char,
-            u8::from_str_radix("0D", 16).unwrap() as char,
-            u8::from_str_radix("0C", 16).unwrap() as char,
-            u8::from_str_radix("20", 16).unwrap() as char,
-            u8::from_str_radix("28", 16).unwrap() as char,
-            u8::from_str_radix("29", 16).unwrap() as char,
-            u8::from_str_radix("3C", 16).unwrap() as char,
-            u8::from_str_radix("3E", 16).unwrap() as char,
-            u8::from_str_radix("5B", 16).unwrap() as char,
-            u8::from_str_radix("5D", 16).unwrap() as char,
-            u8::from_str_radix("7B", 16).unwrap() as char,
-            u8::from_str_radix("7D", 16).unwrap() as char,
-            u8::from_str_radix("2F", 16).unwrap() as char,
-            u8::from_str_radix("25", 16).unwrap() as char,
-            '\\',
-        ];
-        loop {
-            let token: char = reader.peek_next().into();
-            if token == '#' {
-                reader.advance(1);
-                let hex = u8s_to_string(&reader.read(2))?;
-                name.push(u8::from_str_radix(hex, 16).unwrap().into());
-            } else if break_char.contains(&token) {
-                break;
-            } else {
-                reader.advance(1);
-                name.push(token)
-            }
+        let start = expect_int(&mut lexer)?;
+        let count = expect_int(&mut lexer)?;
+        for number in start..start.saturating_add(count) {
+            let field = expect_int(&mut lexer)?;
+            let gen = expect_int(&mut lexer)?;
+            let entry = match lexer.next_token()? {
+                Some(token) if token.kind.is_keyword("n") => XrefEntry::InUse {
+                    offset: field as usize,
+                    gen,
+                },
+                Some(token) if token.kind.is_keyword("f") => XrefEntry::Free { next: field, gen },
+                token => {
+                    let offset = token.map_or(lexer.raw().len(), |x| x.offset);
+                    return Err(
+                        PdfError::xref("Expected xref entry type n or f").at(lexer.raw(), offset)
+                    );
+                }
+            };
+            entries.push((number, entry));
         }
-        Ok(Self::Name(name))
     }
-    fn consume_array(reader: &mut PDFReader) -> io::Result<AnyPDFData> {
-        let mut objects: Vec<AnyPDFData> = vec![];
-        reader.advance(1);
-        loop {
-            reader.skip_whitespace();
-            let token: char = reader.peek_next().into();
-            if token == ']' {
-                reader.advance(1);
-                break;
-            }
-            objects.push(Self::consume(reader)?);
-        }
-        Ok(Self::Array(objects))
+    expect_keyword(&mut lexer, "trailer")?;
+    let offset = lexer.raw().position();
+    let AnyPDFData::Dictionary(trailer) = AnyPDFData::parse(&mut lexer)? else {
+        return Err(PdfError::xref("Trailer must be a dictionary").at(lexer.raw(), offset));
+    };
+    Ok((entries, trailer))
+}
+
+/// Parses a cross-reference stream object (PDF 1.5). Its dictionary doubles
+/// as the trailer of the section.
+fn consume_xref_stream<R: StreamReader>(
+    reader: &mut R,
+) -> Result<(Vec<(i64, XrefEntry)>, Dictionary), PdfError> {
+    let offset = reader.position();
+    let object = Object::consume(reader)?;
+    let AnyPDFData::Stream(stream) = object.data else {
+        return Err(PdfError::xref("Xref stream must be a stream")
+            .at(reader, offset)
+            .in_object(object.number, object.gen));
+    };
+    match xref_stream_entries(&stream) {
+        Ok(entries) => Ok((entries, stream.dict)),
+        Err(e) => Err(e.at(reader, offset).in_object(object.number, object.gen)),
     }
-    fn consume_dict(reader: &mut PDFReader) -> Result<AnyPDFData, io::Error> {
-        reader.advance(2);
-        let mut dict = vec![];
-        loop {
-            reader.skip_whitespace();
-            let peek = u8s_to_string(&reader.peek(2));
-            if peek.is_ok_and(|x| x == ">>") {
-                reader.advance(2);
-                return Ok(Self::Dictionary(dict));
+}
+
+fn xref_stream_entries(stream: &Stream) -> Result<Vec<(i64, XrefEntry)>, PdfError> {
+    let dict = &stream.dict;
+    let widths = dict.get_array("W")?;
+    if widths.len() != 3 {
+        return Err(PdfError::xref("Xref stream /W must have three entries"));
+    }
+    let widths = widths
+        .iter()
+        .map(|x| match x {
+            AnyPDFData::Integer(width @ 0..=8) => Ok(*width as usize),
+            _ => Err(PdfError::xref("Invalid xref stream field width")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let index = match dict.get_array("Index") {
+        Ok(index) => index
+            .iter()
+            .filter_map(|x| match x {
+                AnyPDFData::Integer(int) => Some(*int),
+                _ => None,
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => vec![0, dict.get_int("Size").unwrap_or(0)],
+    };
+    let data = stream.decoded()?;
+    let row_len = widths.iter().sum::<usize>();
+    let mut rows = data.chunks_exact(row_len.max(1));
+    let mut entries = vec![];
+    for subsection in index.chunks_exact(2) {
+        for number in subsection[0]..subsection[0].saturating_add(subsection[1]) {
+            let Some(row) = rows.next() else {
+                return Err(PdfError::xref("Xref stream is shorter than its Index"));
+            };
+            let mut fields = [0i64; 3];
+            let mut position = 0;
+            for (field, width) in fields.iter_mut().zip(&widths) {
+                for byte in &row[position..position + width] {
+                    *field = (*field << 8) | *byte as i64;
+                }
+                position += width;
+            }
+            if widths[0] == 0 {
+                fields[0] = 1;
             }
-            let Ok(AnyPDFData::Name(name)) = Self::consume_name(reader) else {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Dictionary key must be a name",
-                ));
+            let entry = match fields {
+                [0, next, gen] => XrefEntry::Free { next, gen },
+                [1, offset, gen] => XrefEntry::InUse {
+                    offset: offset as usize,
+                    gen,
+                },
+                [2, stream, index] => XrefEntry::Compressed { stream, index },
+                // unknown types are to be treated as references to the null object
+                _ => continue,
             };
-            reader.skip_whitespace();
-            let value = Self::consume(reader)?;
+            entries.push((number, entry));
+        }
+    }
+    Ok(entries)
+}
+
+/// Parses the xref section at the current offset, either a table or a stream.
+/// Hybrid-reference files also list compressed objects in the stream named
+/// by the trailer's /XRefStm, which overrides the table's free entries.
+fn consume_xref_section<R: StreamReader>(
+    reader: &mut R,
+) -> Result<(Vec<(i64, XrefEntry)>, Dictionary), PdfError> {
+    reader.skip_whitespace();
+    if reader.peek(4) != b"xref" {
+        return consume_xref_stream(reader);
+    }
+    let (table, trailer) = consume_xref_table(reader)?;
+    let Ok(xrefstm) = trailer.get_int("XRefStm") else {
+        return Ok((table, trailer));
+    };
+    reader.seek(xrefstm as usize);
+    let (stream, _) = consume_xref_stream(reader)?;
+    let in_use: HashSet<i64> = table
+        .iter()
+        .filter(|x| matches!(x.1, XrefEntry::InUse { .. }))
+        .map(|x| x.0)
+        .collect();
+    let mut entries: Vec<_> = stream
+        .into_iter()
+        .filter(|x| !in_use.contains(&x.0))
+        .collect();
+    entries.extend(table);
+    Ok((entries, trailer))
+}
+
+/// Finds the end of the revision whose xref section starts at `offset`: the
+/// first `%%EOF` marker after it, including its end-of-line.
+fn revision_end<R: StreamReader>(reader: &mut R, offset: usize) -> usize {
+    reader.seek(offset);
+    let Some(eof) = reader.find(b"%%EOF") else {
+        return reader.len();
+    };
+    let mut end = eof + 5;
+    reader.seek(end);
+    if reader.peek(1) == b"\r" {
+        end += 1;
+        reader.seek(end);
+    }
+    if reader.peek(1) == b"\n" {
+        end += 1;
+    }
+    end.min(reader.len())
+}
+
+/// Walks the xref sections starting at `startxref`, following /Prev links,
+/// and returns one revision per section, oldest first.
+fn consume_revisions<R: StreamReader>(
+    reader: &mut R,
+    startxref: usize,
+) -> Result<Vec<Revision>, PdfError> {
+    let mut revisions = vec![];
+    let mut visited = HashSet::new();
+    let mut next = Some(startxref);
+    while let Some(offset) = next {
+        if !visited.insert(offset) {
+            break;
+        }
+        reader.seek(offset);
+        let (entries, trailer) = consume_xref_section(reader)?;
+        let mut changed = BTreeMap::new();
+        for (number, entry) in entries {
+            changed.entry(number).or_insert(entry);
+        }
+        next = trailer
+            .get_int("Prev")
+            .ok()
+            .and_then(|prev| usize::try_from(prev).ok());
+        revisions.push(Revision {
+            range: 0..revision_end(reader, offset),
+            xref_offset: offset,
+            changed,
+            trailer,
+        });
+    }
+    revisions.reverse();
+    // each revision starts where the one written before it ends
+    let ends: Vec<usize> = revisions.iter().map(|x| x.range.end).collect();
+    for revision in &mut revisions {
+        revision.range.start = ends
+            .iter()
+            .copied()
+            .filter(|&end| end < revision.range.end)
+            .max()
+            .unwrap_or(0);
+    }
+    Ok(revisions)
+}
+
+/// Unpacks every object stored in the object stream (/Type /ObjStm) at the
+/// reader's position.
+fn consume_object_stream<R: StreamReader>(
+    reader: &mut R,
+    lenient: bool,
+) -> Result<Vec<Object>, PdfError> {
+    let offset = reader.position();
+    let object = Object::consume_with(reader, lenient)?;
+    let (number, gen) = (object.number, object.gen);
+    let AnyPDFData::Stream(stream) = object.data else {
+        return Err(PdfError::syntax("Object stream must be a stream")
+            .at(reader, offset)
+            .in_object(number, gen));
+    };
+    stream
+        .decoded()
+        .and_then(|data| object_stream_contents(&stream.dict, data, lenient))
+        .map_err(|e| e.at(reader, offset).in_object(number, gen))
+}
+
+/// Parses the decoded data of an object stream, whose dictionary is `dict`.
+/// The data starts with /N pairs of object numbers and offsets relative to
+/// /First, followed by the objects themselves.
+pub fn object_stream_contents(
+    dict: &Dictionary,
+    data: Vec<u8>,
+    lenient: bool,
+) -> Result<Vec<Object>, PdfError> {
+    let count = dict.get_int("N")?;
+    let first = dict.get_int("First")?;
+    let mut reader = PDFReader::from(data);
+    let mut lexer = Lexer::new(&mut reader).lenient(lenient);
+    let mut header = vec![];
+    for _ in 0..count {
+        let contained = expect_int(&mut lexer)?;
+        let offset = expect_int(&mut lexer)?;
+        header.push((contained, offset));
+    }
+    let mut objects = vec![];
+    for (number, offset) in header {
+        lexer.seek(first.saturating_add(offset) as usize);
+        objects.push(Object {
+            number,
+            gen: 0,
+            data: AnyPDFData::parse(&mut lexer).map_err(|e| e.in_object(number, 0))?,
+        });
+    }
+    Ok(objects)
+}
+
+/// Returns where the `N G` of an object header ending with the `obj` keyword
+/// at `position` starts, if there is one.
+fn object_header_start<R: StreamReader>(reader: &mut R, position: usize) -> Option<usize> {
+    static HEADER: OnceLock<BytesRegex> = OnceLock::new();
+    let header = HEADER.get_or_init(|| {
+        BytesRegex::new(r"(?:^|[^0-9])(\d+)[\x00\t\n\x0C\r ]+\d+[\x00\t\n\x0C\r ]+$").unwrap()
+    });
+    let window_start = position.saturating_sub(48);
+    reader.seek(window_start);
+    let window = reader.peek(position - window_start);
+    let number = header.captures(window)?.get(1)?;
+    if number.start() == 0 && window_start != 0 {
+        return None;
+    }
+    Some(window_start + number.start())
+}
 
-            let index = dict.iter().position(|x: &(String, AnyPDFData)| x.0 == name);
-            if let Some(index) = index {
-                dict[index] = (name, value);
-            } else {
-                dict.push((name, value));
+/// A reader which ends at `end`, so that parsing a damaged object, such as
+/// one with an unterminated string, cannot scan the rest of the file.
+struct Window<'r, R> {
+    reader: &'r mut R,
+    end: usize,
+}
+
+impl<R: StreamReader> StreamReader for Window<'_, R> {
+    fn len(&self) -> usize {
+        self.end.min(self.reader.len())
+    }
+    fn position(&self) -> usize {
+        self.reader.position().min(self.len())
+    }
+    fn seek(&mut self, offset: usize) {
+        self.reader.seek(offset.min(self.end));
+    }
+    fn slice(&mut self, range: Range<usize>) -> &[u8] {
+        let end = self.end;
+        self.reader.slice(range.start.min(end)..range.end.min(end))
+    }
+}
+
+/// Rebuilds the object index by scanning the whole file for `N G obj`
+/// headers, wherever they appear. Later copies of an object replace earlier
+/// ones, since incremental updates are appended to the file. Objects inside
+/// object streams are indexed too, unless they are also defined at the top
+/// level. Each object is parsed no further than the next header, which keeps
+/// the scan linear however damaged the file is.
+fn rebuild_xref<R: StreamReader>(reader: &mut R) -> (BTreeMap<i64, XrefEntry>, Dictionary) {
+    let mut xref = BTreeMap::new();
+    let mut object_streams = vec![];
+    let mut xref_stream_dict = None;
+    let mut headers = vec![];
+    reader.seek(0);
+    while let Some(position) = reader.find(b"obj") {
+        reader.seek(position + 3);
+        let terminated =
+            reader.at_eof() || b"\x00\t\n\x0C\r ()<>[]{}/%".contains(&reader.peek_next());
+        if let Some(start) = object_header_start(reader, position).filter(|_| terminated) {
+            headers.push(start);
+        }
+        reader.seek(position + 3);
+    }
+    // where the last object parsed ends; headers before it are inside it
+    let mut parsed_to = 0;
+    for (i, &start) in headers.iter().enumerate() {
+        if start < parsed_to {
+            continue;
+        }
+        let end = headers.get(i + 1).copied().unwrap_or(reader.len());
+        reader.seek(start);
+        let mut window = Window { reader, end };
+        let Ok(object) = Object::consume_with(&mut window, true) else {
+            continue;
+        };
+        parsed_to = window.position();
+        if let AnyPDFData::Stream(Stream { dict, .. }) = &object.data {
+            match dict.get_name("Type").ok().and_then(Name::as_str) {
+                Some("ObjStm") => object_streams.push(object.number),
+                Some("XRef") => xref_stream_dict = Some(dict.clone()),
+                _ => {}
             }
         }
+        xref.insert(
+            object.number,
+            XrefEntry::InUse {
+                offset: start,
+                gen: object.gen,
+            },
+        );
     }
-    fn consume_objref(reader: &mut PDFReader) -> Option<AnyPDFData> {
-        // assuming good
-        let string = u8s_to_string(&reader.read_until(b'R')).unwrap(); // Clone the data to avoid borrow issues
-        let numbers: Vec<i64> = string
-            .split(' ')
-            .filter_map(|x| x.parse::<i64>().ok())
-            .collect();
-        Some(Self::ObjRef(numbers[0], numbers[1]))
-    }
-    fn consume_stream(reader: &mut PDFReader, mapobj: AnyPDFData) -> Result<AnyPDFData, io::Error> {
-        let AnyPDFData::Dictionary(dict) = mapobj else {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Stream must be preceded by a dictionary",
-            ));
+    let mut compressed = BTreeMap::new();
+    for stream in object_streams {
+        let Some(XrefEntry::InUse { offset, .. }) = xref.get(&stream) else {
+            continue;
         };
-        let AnyPDFData::Integer(length) = dict
-            .iter()
-            .find(|x| x.0 == "Length")
-            .expect("Stream must have a Length key")
-            .1
-        else {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Stream must have a Length key",
-            ));
+        reader.seek(*offset);
+        let Ok(objects) = consume_object_stream(reader, true) else {
+            continue;
         };
-        reader.advance(6);
-        if reader.peek_next() == b'\r' {
-            reader.advance(1);
+        for (index, object) in objects.iter().enumerate() {
+            compressed.insert(
+                object.number,
+                XrefEntry::Compressed {
+                    stream,
+                    index: index as i64,
+                },
+            );
         }
-        reader.advance(1);
-        let stream: Vec<u8> = reader.read(length as usize).into();
-        reader.skip_whitespace();
-        reader.advance(9);
-        Ok(AnyPDFData::Stream(dict, stream))
-    }
-    fn consume(reader: &mut PDFReader) -> Result<AnyPDFData, io::Error> {
-        reader.skip_whitespace();
-        match reader {
-            t if t.peek(2) == "<<".as_bytes() => {
-                let object = Self::consume_dict(t);
-                t.skip_whitespace();
-                if t.peek(6) == "stream".as_bytes() {
-                    Self::consume_stream(t, object?)
-                } else {
-                    object
-                }
-            }
-            t if t.peek(1) == "t".as_bytes() || t.peek(1) == "f".as_bytes() => {
-                Self::consume_bool(t)
+    }
+    for (number, entry) in compressed {
+        xref.entry(number).or_insert(entry);
+    }
+    let mut trailer = reader
+        .rfind(b"trailer")
+        .and_then(|position| {
+            reader.seek(position + 7);
+            match AnyPDFData::parse(&mut Lexer::new(reader).lenient(true)) {
+                Ok(AnyPDFData::Dictionary(dict)) => Some(dict),
+                _ => None,
             }
-            t if {
-                let regex = Regex::new(r"^\d+\s+\d+\s+$").unwrap();
-                let string = u8s_to_string(&t.peek_until(b'R')); // Clone the data to avoid borrow issues
-                if string.is_err() {
-                    false
-                } else {
-                    let unwrapped = string.unwrap();
-                    regex.is_match(&unwrapped)
+        })
+        .or(xref_stream_dict)
+        .unwrap_or_default();
+    trailer.retain(|key, _| key != "Prev" && key != "XRefStm");
+    if !trailer.contains_key("Root") {
+        let mut objects = load_objects(reader, &xref, true).unwrap_or_default();
+        objects.retain(|object| match &object.data {
+            AnyPDFData::Dictionary(dict) => dict.get_name("Type").is_ok_and(|x| x == "Catalog"),
+            _ => false,
+        });
+        if let Some(catalog) = objects.last() {
+            trailer.insert("Root", AnyPDFData::ObjRef(catalog.number, catalog.gen));
+        }
+    }
+    let size = xref
+        .keys()
+        .next_back()
+        .map_or(0, |number| number.saturating_add(1));
+    trailer.insert("Size", AnyPDFData::Integer(size));
+    (xref, trailer)
+}
+
+/// Finds object `number` among the objects unpacked from an object stream,
+/// where the xref says it is at `index`.
+pub fn find_compressed(contained: &[Object], number: i64, index: i64) -> Option<&Object> {
+    contained
+        .get(index as usize)
+        .filter(|x| x.number == number)
+        .or_else(|| contained.iter().find(|x| x.number == number))
+}
+
+fn consume_indexed_object_stream<R: StreamReader>(
+    reader: &mut R,
+    xref: &BTreeMap<i64, XrefEntry>,
+    stream: i64,
+    lenient: bool,
+) -> Result<Vec<Object>, PdfError> {
+    let Some(XrefEntry::InUse { offset, .. }) = xref.get(&stream) else {
+        return Err(PdfError::resolution(format!(
+            "Object stream {} is not in use",
+            stream
+        )));
+    };
+    reader.seek(*offset);
+    consume_object_stream(reader, lenient)
+}
+
+/// Loads a single object through the index, seeking straight to it rather
+/// than reading the rest of the file. Free and unknown objects give `None`.
+pub fn load_object<R: StreamReader>(
+    reader: &mut R,
+    xref: &BTreeMap<i64, XrefEntry>,
+    number: i64,
+) -> Result<Option<Object>, PdfError> {
+    load_object_with(reader, xref, number, false)
+}
+
+fn load_object_with<R: StreamReader>(
+    reader: &mut R,
+    xref: &BTreeMap<i64, XrefEntry>,
+    number: i64,
+    lenient: bool,
+) -> Result<Option<Object>, PdfError> {
+    match xref.get(&number) {
+        Some(XrefEntry::InUse { offset, .. }) => {
+            reader.seek(*offset);
+            Object::consume_with(reader, lenient).map(Some)
+        }
+        Some(XrefEntry::Compressed { stream, index }) => {
+            let contained = consume_indexed_object_stream(reader, xref, *stream, lenient)
+                .map_err(|e| e.in_object(number, 0))?;
+            Ok(find_compressed(&contained, number, *index).cloned())
+        }
+        Some(XrefEntry::Free { .. }) | None => Ok(None),
+    }
+}
+
+/// Loads every in-use object listed in the index, in object number order.
+/// When `lenient`, objects which fail to parse are left out instead of
+/// failing the whole load, and a missing `endobj` is tolerated.
+fn load_objects<R: StreamReader>(
+    reader: &mut R,
+    xref: &BTreeMap<i64, XrefEntry>,
+    lenient: bool,
+) -> Result<Vec<Object>, PdfError> {
+    let mut objects = vec![];
+    let mut object_streams: BTreeMap<i64, Vec<Object>> = BTreeMap::new();
+    for (&number, entry) in xref {
+        let object = match *entry {
+            XrefEntry::InUse { .. } => load_object_with(reader, xref, number, lenient),
+            XrefEntry::Compressed { stream, index } => {
+                if let Entry::Vacant(slot) = object_streams.entry(stream) {
+                    let contained =
+                        match consume_indexed_object_stream(reader, xref, stream, lenient) {
+                            Ok(contained) => contained,
+                            Err(_) if lenient => vec![],
+                            Err(e) => return Err(e),
+                        };
+                    slot.insert(contained);
                 }
-            } =>
-            {
-                Ok(Self::consume_objref(t).unwrap())
+                Ok(find_compressed(&object_streams[&stream], number, index).cloned())
             }
-            t if "-.0123456789+".contains(t.peek_next() as char) => Self::consume_num(t),
-            t if t.peek(1) == "(".as_bytes() => Self::consume_lit_str(t),
-            t if t.peek(1) == "<".as_bytes() => Ok(Self::consume_hex_str(t)),
-            t if t.peek(1) == "/".as_bytes() => Self::consume_name(t),
-            t if t.peek(1) == "[".as_bytes() => Self::consume_array(t),
-            _t => {
-                // dbg!(u8s_to_string(&_t.clone().peek(100)));
-                Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Unable to parse object",
-                ))
+            XrefEntry::Free { .. } => Ok(None),
+        };
+        match object {
+            Ok(Some(object)) => objects.push(object),
+            Ok(None) => {}
+            Err(_) if lenient => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(objects)
+}
+
+/// Checks that every in-use entry of the index points at the header of the
+/// object it lists, which tells an unusable index apart from one with a few
+/// damaged objects.
+fn check_index<R: StreamReader>(
+    reader: &mut R,
+    xref: &BTreeMap<i64, XrefEntry>,
+) -> Result<(), PdfError> {
+    for (&number, entry) in xref {
+        let XrefEntry::InUse { offset, .. } = *entry else {
+            continue;
+        };
+        reader.seek(offset);
+        let mut window = Window {
+            reader: &mut *reader,
+            end: offset.saturating_add(64),
+        };
+        let mut lexer = Lexer::new(&mut window);
+        let header = expect_int(&mut lexer)
+            .and_then(|found| Ok((found, expect_int(&mut lexer)?)))
+            .and_then(|header| expect_keyword(&mut lexer, "obj").map(|_| header));
+        match header {
+            Ok((found, _)) if found == number => {}
+            _ => {
+                return Err(PdfError::xref(format!(
+                    "Object {} is not at offset {}",
+                    number, offset
+                )))
             }
         }
     }
+    Ok(())
+}
+
+/// Reads the version from the `%PDF-x.y` header, which may be preceded by
+/// junk and end with any kind of end-of-line.
+fn consume_header<R: StreamReader>(reader: &mut R) -> Option<String> {
+    reader.seek(0);
+    let start = reader.find(b"%PDF-").filter(|&start| start < 1024)?;
+    reader.seek(start);
+    let line = reader
+        .peek(64)
+        .split(|x| *x == b'\r' || *x == b'\n')
+        .next()?;
+    u8s_to_string(line).ok().map(|x| x.trim_end().to_string())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParseOptions {
+    /// Rebuild the object index by scanning the file when the cross-reference
+    /// data is missing or unusable, and leave out objects which fail to
+    /// parse, instead of failing.
+    pub lenient: bool,
+    /// Load every object up front. Without it only the index, trailer and
+    /// revisions are read, and objects are fetched later with `load_object`.
+    pub load_objects: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            lenient: true,
+            load_objects: true,
+        }
+    }
 }
 
-pub fn parse(reader: &mut PDFReader) -> Result<PDF, io::Error> {
+impl ParseOptions {
+    pub fn strict() -> Self {
+        ParseOptions {
+            lenient: false,
+            ..Default::default()
+        }
+    }
+}
+
+/// Reads the index through the xref sections chained from `startxref`.
+fn parse_xref<R: StreamReader>(reader: &mut R, pdf: &mut PDF) -> Result<(), PdfError> {
+    let Some(startxref) = find_startxref(reader) else {
+        return Err(PdfError::xref("Missing startxref"));
+    };
+    pdf.revisions = consume_revisions(reader, startxref)?;
+    // the newest definition of each object wins
+    for revision in pdf.revisions.iter().rev() {
+        for (&number, &entry) in &revision.changed {
+            pdf.xref.entry(number).or_insert(entry);
+        }
+    }
+    if let Some(newest) = pdf.revisions.last() {
+        pdf.trailer = newest.trailer.clone();
+    }
+    Ok(())
+}
+
+pub fn parse<R: StreamReader>(reader: &mut R) -> Result<PDF, PdfError> {
+    parse_with(reader, ParseOptions::default())
+}
+
+pub fn parse_with<R: StreamReader>(reader: &mut R, options: ParseOptions) -> Result<PDF, PdfError> {
+    if reader.is_empty() {
+        return Err(PdfError::truncated("File is empty"));
+    }
+    let version = match consume_header(reader) {
+        Some(version) => version,
+        None if options.lenient => String::new(),
+        None => return Err(PdfError::syntax("Missing %PDF- header")),
+    };
     let mut pdf = PDF {
-        version: u8s_to_string(&reader.read_until('\n' as u8))?.to_string(),
+        version,
         objects: vec![],
+        xref: BTreeMap::new(),
+        trailer: Dictionary::new(),
+        revisions: vec![],
+        recovered: None,
     };
-    let obj_beginning = Regex::new(r"^\d+\s+\d+\s+obj").unwrap();
-    while !reader.at_eof() {
-        // dbg!(reader.bytes.len() - reader.offset);
-        // dbg!(reader.offset, u8s_to_string(&reader.peek(100)));
-        let line = u8s_to_string(&reader.read_until('\n' as u8));
-        if line.is_err() {
-            continue;
+    // a sound index is kept even when some of its objects are damaged; those
+    // are left out when lenient
+    let mut loaded = parse_xref(reader, &mut pdf);
+    if loaded.is_ok() && options.load_objects {
+        loaded = match check_index(reader, &pdf.xref) {
+            Err(e) if options.lenient => Err(e),
+            _ => load_objects(reader, &pdf.xref, options.lenient)
+                .map(|objects| pdf.objects = objects),
+        };
+    }
+    if let Err(e) = loaded {
+        if !options.lenient {
+            return Err(e);
         }
-        let line = line.unwrap();
-        if obj_beginning.is_match(&line) {
-            let obj_info = obj_beginning
-                .find(&line)
-                .unwrap()
-                .as_str()
-                .split_whitespace()
-                .filter_map(|x| x.parse::<i64>().ok())
-                .collect::<Vec<_>>();
-            let object = Object {
-                number: obj_info[0],
-                gen: obj_info[1],
-                data: AnyPDFData::consume(reader)?,
-            };
-            if let AnyPDFData::Stream(data, values) = &object.data {
-                fn decode_reader(bytes: Vec<u8>) -> io::Result<String> {
-                    let mut deflater = ZlibDecoder::new(&bytes[..]);
-                    let mut s = String::new();
-                    deflater.read_to_string(&mut s)?;
-                    Ok(s)
-                }
-                let result = decode_reader(values.clone());
-                match result {
-                    Ok(x) => {
-                        dbg!(x);
-                        ()
-                    }
-                    Err(x) => {
-                        // dbg!(data);
-                        ()
-                    }
-                }
-            }
-            pdf.objects.push(object.clone());
+        let (xref, trailer) = rebuild_xref(reader);
+        pdf.xref = xref;
+        pdf.trailer = trailer;
+        pdf.revisions = vec![];
+        pdf.recovered = Some(e.to_string());
+        if options.load_objects {
+            pdf.objects = load_objects(reader, &pdf.xref, true)?;
         }
     }
     Ok(pdf)
@@ -1,99 +1,280 @@
+use crate::lexer::is_whitespace;
+use memmap2::Mmap;
 use std::io::Read;
+use std::ops::{Deref, Range};
+use std::sync::Arc;
 use std::*;
 
 pub fn u8s_to_string(bytes: &[u8]) -> Result<&str, io::Error> {
     str::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
 
-pub trait StreamReader: Clone + From<fs::File> + From<String> {
-    fn advance(&mut self, amt: usize);
-    fn read(&mut self, amt: usize) -> &[u8];
-    fn at_eof(&self) -> bool;
-    fn peek(&self, amt: usize) -> &[u8];
-    fn peek_next(&self) -> u8;
-    fn next(&mut self) -> u8;
-    fn skip_whitespace(&mut self);
-    fn peek_until(&self, byte: u8) -> &[u8];
-    fn read_until(&mut self, byte: u8) -> &[u8];
+/// A cursor over the bytes of a PDF file. Implementors only provide random
+/// access to the underlying bytes; everything else is built on top of that.
+pub trait StreamReader {
+    fn len(&self) -> usize;
+    fn position(&self) -> usize;
+    /// Moves the cursor, clamped to the end of the file.
+    fn seek(&mut self, offset: usize);
+    /// Returns the bytes in `range`, cut short at the end of the file, without
+    /// moving the cursor.
+    fn slice(&mut self, range: Range<usize>) -> &[u8];
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn at_eof(&self) -> bool {
+        self.position() >= self.len()
+    }
+    fn advance(&mut self, amt: usize) {
+        self.seek(self.position() + amt);
+    }
+    fn read(&mut self, amt: usize) -> &[u8] {
+        let start = self.position();
+        self.advance(amt);
+        let end = self.position();
+        self.slice(start..end)
+    }
+    fn peek(&mut self, amt: usize) -> &[u8] {
+        let start = self.position();
+        self.slice(start..start + amt)
+    }
+    fn peek_next(&mut self) -> u8 {
+        self.peek(1).first().copied().unwrap_or(0)
+    }
+    fn next(&mut self) -> u8 {
+        let next = self.peek_next();
+        self.advance(1);
+        next
+    }
+    fn skip_whitespace(&mut self) {
+        while !self.at_eof() && is_whitespace(self.peek_next()) {
+            self.advance(1);
+        }
+    }
+    /// Finds the first occurrence of `pattern` at or after the cursor.
+    fn find(&mut self, pattern: &[u8]) -> Option<usize> {
+        const CHUNK: usize = 4096;
+        let mut start = self.position();
+        while start < self.len() {
+            let chunk = self.slice(start..start + CHUNK + pattern.len());
+            if let Some(position) = chunk.windows(pattern.len()).position(|x| x == pattern) {
+                return Some(start + position);
+            }
+            start += CHUNK;
+        }
+        None
+    }
+    /// Finds the last occurrence of `pattern` in the whole file.
+    fn rfind(&mut self, pattern: &[u8]) -> Option<usize> {
+        const CHUNK: usize = 4096;
+        let mut end = self.len();
+        while end >= pattern.len() {
+            let start = end.saturating_sub(CHUNK + pattern.len());
+            let chunk = self.slice(start..end);
+            if let Some(position) = chunk.windows(pattern.len()).rposition(|x| x == pattern) {
+                return Some(start + position);
+            }
+            if start == 0 {
+                break;
+            }
+            end = start + pattern.len();
+        }
+        None
+    }
 }
 
-pub struct PDFReader {
-    pub bytes: &'static [u8],
+/// The bytes of a `PDFReader`, either borrowed from the caller, owned by the
+/// reader or mapped from a file.
+#[derive(Clone)]
+pub enum Buffer<'a> {
+    Borrowed(&'a [u8]),
+    Owned(Vec<u8>),
+    Shared(Arc<[u8]>),
+    Mapped(Arc<Mmap>),
+}
+
+impl Deref for Buffer<'_> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            Buffer::Borrowed(bytes) => bytes,
+            Buffer::Owned(bytes) => bytes,
+            Buffer::Shared(bytes) => bytes,
+            Buffer::Mapped(map) => map,
+        }
+    }
+}
+
+/// Reads a PDF held entirely in memory.
+#[derive(Clone)]
+pub struct PDFReader<'a> {
+    pub bytes: Buffer<'a>,
     pub offset: usize,
 }
 
-impl From<fs::File> for PDFReader {
-    fn from(file: fs::File) -> Self {
-        let buf: Vec<u8> = file
-            .try_clone()
-            .unwrap()
-            .bytes()
-            .filter_map(Result::ok)
-            .collect();
+impl PDFReader<'static> {
+    pub fn open(path: impl AsRef<path::Path>) -> io::Result<Self> {
+        let mut buf: Vec<u8> = vec![];
+        fs::File::open(path)?.read_to_end(&mut buf)?;
+        Ok(Self::from(buf))
+    }
+
+    /// Memory-maps the file at `path` instead of copying it onto the heap,
+    /// which is much cheaper for large files.
+    ///
+    /// The file must not be modified or truncated while the reader (or any
+    /// clone of it) is alive; doing so is undefined behaviour.
+    pub fn map(path: impl AsRef<path::Path>) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        // SAFETY: the caller guarantees the file is not changed while mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Self::from(map))
+    }
+}
+
+impl From<Mmap> for PDFReader<'static> {
+    fn from(map: Mmap) -> Self {
         Self {
-            bytes: Box::leak(buf.into_boxed_slice()),
+            bytes: Buffer::Mapped(Arc::new(map)),
             offset: 0,
         }
     }
 }
 
-impl From<String> for PDFReader {
+impl From<String> for PDFReader<'static> {
     fn from(s: String) -> Self {
+        Self::from(s.into_bytes())
+    }
+}
+
+impl From<Vec<u8>> for PDFReader<'static> {
+    fn from(bytes: Vec<u8>) -> Self {
         Self {
-            bytes: Box::leak(s.into_bytes().into_boxed_slice()),
+            bytes: Buffer::Owned(bytes),
             offset: 0,
         }
     }
 }
-impl Clone for PDFReader {
-    fn clone(&self) -> Self {
-        PDFReader {
-            bytes: self.bytes,
-            offset: self.offset,
+
+impl From<Arc<[u8]>> for PDFReader<'static> {
+    fn from(bytes: Arc<[u8]>) -> Self {
+        Self {
+            bytes: Buffer::Shared(bytes),
+            offset: 0,
         }
     }
 }
 
-impl StreamReader for PDFReader {
-    fn advance(&mut self, amt: usize) {
-        self.offset = (self.offset + amt).min(self.bytes.len() - 1);
+impl<'a> From<&'a [u8]> for PDFReader<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes: Buffer::Borrowed(bytes),
+            offset: 0,
+        }
     }
-    fn at_eof(&self) -> bool {
-        self.offset >= self.bytes.len() - 1
+}
+
+impl StreamReader for PDFReader<'_> {
+    fn len(&self) -> usize {
+        self.bytes.len()
     }
-    fn read(&mut self, amt: usize) -> &[u8] {
-        let start = self.offset;
-        self.advance(amt);
-        &self.bytes[start..self.offset]
+    fn position(&self) -> usize {
+        self.offset
     }
-    fn peek(&self, amt: usize) -> &[u8] {
-        &self.bytes[self.offset..(self.offset + amt).min(self.bytes.len())]
+    fn seek(&mut self, offset: usize) {
+        self.offset = offset.min(self.bytes.len());
     }
-    fn peek_next(&self) -> u8 {
-        self.bytes[self.offset]
+    fn slice(&mut self, range: Range<usize>) -> &[u8] {
+        let end = range.end.min(self.bytes.len());
+        &self.bytes[range.start.min(end)..end]
     }
-    fn next(&mut self) -> u8 {
-        let next = self.bytes[self.offset];
-        self.advance(1);
-        next
+    fn find(&mut self, pattern: &[u8]) -> Option<usize> {
+        self.bytes[self.offset..]
+            .windows(pattern.len())
+            .position(|window| window == pattern)
+            .map(|position| self.offset + position)
     }
-    fn skip_whitespace(&mut self) {
-        while self.peek_next().is_ascii_whitespace() {
-            self.advance(1);
+    fn rfind(&mut self, pattern: &[u8]) -> Option<usize> {
+        self.bytes
+            .windows(pattern.len())
+            .rposition(|window| window == pattern)
+    }
+}
+
+/// Reads a PDF from any `Read + Seek` source, keeping only a window of the
+/// file in memory. The window is refilled by seeking whenever the parser
+/// looks outside of it.
+pub struct SeekReader<S: io::Read + io::Seek> {
+    source: S,
+    len: usize,
+    offset: usize,
+    window: Vec<u8>,
+    window_start: usize,
+    capacity: usize,
+    error: Option<io::Error>,
+}
+
+impl<S: io::Read + io::Seek> SeekReader<S> {
+    pub fn new(source: S) -> io::Result<Self> {
+        Self::with_capacity(source, 64 * 1024)
+    }
+    /// Creates a reader whose window holds at least `capacity` bytes. Larger
+    /// reads, such as long streams, grow the window only for as long as they
+    /// need to.
+    pub fn with_capacity(mut source: S, capacity: usize) -> io::Result<Self> {
+        let len = source.seek(io::SeekFrom::End(0))? as usize;
+        Ok(Self {
+            source,
+            len,
+            offset: 0,
+            window: vec![],
+            window_start: 0,
+            capacity: capacity.max(1),
+            error: None,
+        })
+    }
+    /// Returns the last error raised by the source. Reads that fail are cut
+    /// short, so parsing will usually fail too.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+    fn fill(&mut self, start: usize, end: usize) {
+        let amt = (end - start).max(self.capacity).min(self.len - start);
+        self.window.clear();
+        self.window_start = start;
+        let result = self
+            .source
+            .seek(io::SeekFrom::Start(start as u64))
+            .and_then(|_| {
+                (&mut self.source)
+                    .take(amt as u64)
+                    .read_to_end(&mut self.window)
+            });
+        if let Err(e) = result {
+            self.error = Some(e);
         }
     }
-    fn peek_until(&self, byte: u8) -> &[u8] {
-        let end = (self.offset..self.bytes.len())
-            .find(|&i| self.bytes[i] == byte)
-            .unwrap_or(self.offset);
-        &self.bytes[self.offset..end]
-    }
-    fn read_until(&mut self, byte: u8) -> &[u8] {
-        let start = self.offset;
-        let end = (self.offset..self.bytes.len())
-            .find(|&i| self.bytes[i] == byte)
-            .unwrap_or(self.offset);
-        self.offset = (end + 1).min(self.bytes.len());
-        &self.bytes[start..end]
+}
+
+impl<S: io::Read + io::Seek> StreamReader for SeekReader<S> {
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn position(&self) -> usize {
+        self.offset
+    }
+    fn seek(&mut self, offset: usize) {
+        self.offset = offset.min(self.len);
+    }
+    fn slice(&mut self, range: Range<usize>) -> &[u8] {
+        let end = range.end.min(self.len);
+        let start = range.start.min(end);
+        if start < self.window_start || end > self.window_start + self.window.len() {
+            self.fill(start, end);
+        }
+        let from = (start - self.window_start).min(self.window.len());
+        let to = (end - self.window_start).min(self.window.len());
+        &self.window[from..to]
     }
 }
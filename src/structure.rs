@@ -1,7 +1,52 @@
+use crate::encoding::decode_text;
+use crate::error::PdfError;
+use crate::filter::{self, DecodeOptions};
+use crate::lexer::is_regular;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+
 #[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct PDF {
     pub version: String,
     pub objects: Vec<Object>,
+    pub xref: BTreeMap<i64, XrefEntry>,
+    pub trailer: Dictionary,
+    /// Every saved revision of the file, oldest first.
+    pub revisions: Vec<Revision>,
+    /// Why the object index had to be rebuilt by scanning the file, if it
+    /// was. `xref` then holds the rebuilt index.
+    pub recovered: Option<String>,
+}
+/// One save of the document: the original file or an incremental update.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Revision {
+    /// The bytes of the file written by this save, ending after its `%%EOF`.
+    pub range: Range<usize>,
+    pub xref_offset: usize,
+    /// The xref entries of this revision, i.e. the objects it added, changed
+    /// or freed.
+    pub changed: BTreeMap<i64, XrefEntry>,
+    pub trailer: Dictionary,
+}
+/// A single entry of the cross-reference index, keyed by object number.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XrefEntry {
+    Free {
+        next: i64,
+        gen: i64,
+    },
+    InUse {
+        offset: usize,
+        gen: i64,
+    },
+    /// An object stored at `index` inside the object stream `stream`.
+    Compressed {
+        stream: i64,
+        index: i64,
+    },
 }
 // impl Eq for PDF {}
 #[derive(Debug, PartialEq)]
@@ -12,19 +57,21 @@ pub struct Object {
 }
 #[derive(Debug, PartialEq)]
 pub enum AnyPDFData {
+    Null,
     Boolean(bool),
     Integer(i64),
     Real(f64),
-    Name(String),
-    String(String),
+    Name(Name),
+    String(PdfString),
     Array(Vec<AnyPDFData>),
-    Dictionary(Vec<(String, AnyPDFData)>),
-    Stream(Vec<(String, AnyPDFData)>, Vec<u8>),
+    Dictionary(Dictionary),
+    Stream(Stream),
     ObjRef(i64, i64),
 }
 impl Clone for AnyPDFData {
     fn clone(&self) -> Self {
         match self {
+            AnyPDFData::Null => AnyPDFData::Null,
             AnyPDFData::Boolean(b) => AnyPDFData::Boolean(*b),
             AnyPDFData::Integer(i) => AnyPDFData::Integer(*i),
             AnyPDFData::Real(r) => AnyPDFData::Real(*r),
@@ -32,12 +79,332 @@ impl Clone for AnyPDFData {
             AnyPDFData::String(s) => AnyPDFData::String(s.clone()),
             AnyPDFData::Array(a) => AnyPDFData::Array(a.clone()),
             AnyPDFData::Dictionary(d) => AnyPDFData::Dictionary(d.clone()),
-            AnyPDFData::Stream(a, b) => AnyPDFData::Stream(a.clone(), b.clone()),
+            AnyPDFData::Stream(s) => AnyPDFData::Stream(s.clone()),
             AnyPDFData::ObjRef(n, g) => AnyPDFData::ObjRef(*n, *g),
         }
     }
 }
 
+/// A stream object: its dictionary and its data, still encoded with the
+/// filters the dictionary names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stream {
+    pub dict: Dictionary,
+    pub data: Vec<u8>,
+}
+impl Stream {
+    /// The stream data with every filter in /Filter undone, in order,
+    /// except that JPEG images are left as JPEG data. Nothing here knows
+    /// whether the document is encrypted, so encrypted data gives whatever
+    /// error its filters make of it; `Document::decode_stream` checks first.
+    pub fn decoded(&self) -> Result<Vec<u8>, PdfError> {
+        filter::decode(&self.dict, &self.data)
+    }
+    pub fn decoded_with(&self, options: DecodeOptions) -> Result<Vec<u8>, PdfError> {
+        filter::decode_with(&self.dict, &self.data, options)
+    }
+}
+
+/// A name object, without its leading slash and with `#xx` escapes decoded.
+/// Names are sequences of bytes which are usually, but not always, UTF-8.
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Name(Vec<u8>);
+
+impl Name {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+    /// The name as text, if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.0).ok()
+    }
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.0).into_owned()
+    }
+}
+
+/// Writes the name as it would appear in a file, starting with `/` and with
+/// every byte that is not a regular printable character escaped as `#xx`.
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "/")?;
+        for &byte in &self.0 {
+            if (0x21..=0x7E).contains(&byte) && is_regular(byte) && byte != b'#' {
+                write!(f, "{}", byte as char)?;
+            } else {
+                write!(f, "#{:02X}", byte)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl From<Vec<u8>> for Name {
+    fn from(bytes: Vec<u8>) -> Self {
+        Name(bytes)
+    }
+}
+
+impl From<&[u8]> for Name {
+    fn from(bytes: &[u8]) -> Self {
+        Name(bytes.to_vec())
+    }
+}
+
+impl From<&str> for Name {
+    fn from(name: &str) -> Self {
+        Name(name.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Name {
+    fn from(name: String) -> Self {
+        Name(name.into_bytes())
+    }
+}
+
+impl AsRef<[u8]> for Name {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Borrow<[u8]> for Name {
+    fn borrow(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for Name {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for Name {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+/// How a string was written in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringFormat {
+    /// `(literal)`
+    Literal,
+    /// `<hex>`
+    Hex,
+}
+/// The bytes of a string object, exactly as they decode from the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdfString {
+    pub bytes: Vec<u8>,
+    pub format: StringFormat,
+}
+impl PdfString {
+    pub fn literal(bytes: impl Into<Vec<u8>>) -> Self {
+        PdfString {
+            bytes: bytes.into(),
+            format: StringFormat::Literal,
+        }
+    }
+    pub fn hex(bytes: impl Into<Vec<u8>>) -> Self {
+        PdfString {
+            bytes: bytes.into(),
+            format: StringFormat::Hex,
+        }
+    }
+    /// Decodes the string as a text string, following its byte order mark
+    /// if it has one.
+    pub fn to_text(&self) -> String {
+        decode_text(&self.bytes)
+    }
+}
+
+impl AnyPDFData {
+    /// What kind of object this is, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            AnyPDFData::Null => "null",
+            AnyPDFData::Boolean(_) => "boolean",
+            AnyPDFData::Integer(_) => "integer",
+            AnyPDFData::Real(_) => "real",
+            AnyPDFData::Name(_) => "name",
+            AnyPDFData::String(_) => "string",
+            AnyPDFData::Array(_) => "array",
+            AnyPDFData::Dictionary(_) => "dictionary",
+            AnyPDFData::Stream(..) => "stream",
+            AnyPDFData::ObjRef(..) => "reference",
+        }
+    }
+}
+
+/// A dictionary object. Entries keep the order they were written in, and
+/// keys are also indexed for constant-time lookup.
+#[derive(Clone, Default)]
+pub struct Dictionary {
+    entries: Vec<(Name, AnyPDFData)>,
+    index: HashMap<Name, usize>,
+}
+
+impl Dictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<&AnyPDFData> {
+        self.index.get(key.as_ref()).map(|&i| &self.entries[i].1)
+    }
+    pub fn get_mut(&mut self, key: impl AsRef<[u8]>) -> Option<&mut AnyPDFData> {
+        self.index
+            .get(key.as_ref())
+            .map(|&i| &mut self.entries[i].1)
+    }
+    pub fn contains_key(&self, key: impl AsRef<[u8]>) -> bool {
+        self.index.contains_key(key.as_ref())
+    }
+    /// Sets `key`, keeping its position if it is already present. Returns
+    /// the value it replaces.
+    pub fn insert(&mut self, key: impl Into<Name>, value: AnyPDFData) -> Option<AnyPDFData> {
+        let key = key.into();
+        match self.index.get(&key) {
+            Some(&i) => Some(std::mem::replace(&mut self.entries[i].1, value)),
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) -> Option<AnyPDFData> {
+        let i = self.index.remove(key.as_ref())?;
+        let (_, value) = self.entries.remove(i);
+        for position in self.index.values_mut() {
+            if *position > i {
+                *position -= 1;
+            }
+        }
+        Some(value)
+    }
+    pub fn retain(&mut self, mut keep: impl FnMut(&Name, &AnyPDFData) -> bool) {
+        self.entries.retain(|(key, value)| keep(key, value));
+        self.reindex();
+    }
+    fn reindex(&mut self) {
+        self.index = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, (key, _))| (key.clone(), i))
+            .collect();
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&Name, &AnyPDFData)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+    pub fn keys(&self) -> impl Iterator<Item = &Name> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    fn require(&self, key: &str) -> Result<&AnyPDFData, PdfError> {
+        self.get(key)
+            .ok_or_else(|| PdfError::syntax(format!("Missing {}", Name::from(key))))
+    }
+    fn mismatch(key: &str, expected: &str, found: &AnyPDFData) -> PdfError {
+        PdfError::syntax(format!(
+            "Expected {} to be {}, found {}",
+            Name::from(key),
+            expected,
+            found.type_name()
+        ))
+    }
+    pub fn get_name(&self, key: &str) -> Result<&Name, PdfError> {
+        match self.require(key)? {
+            AnyPDFData::Name(name) => Ok(name),
+            other => Err(Self::mismatch(key, "a name", other)),
+        }
+    }
+    pub fn get_int(&self, key: &str) -> Result<i64, PdfError> {
+        match self.require(key)? {
+            AnyPDFData::Integer(int) => Ok(*int),
+            other => Err(Self::mismatch(key, "an integer", other)),
+        }
+    }
+    /// Reads a number, which PDF allows to be written either way.
+    pub fn get_real_or_int(&self, key: &str) -> Result<f64, PdfError> {
+        match self.require(key)? {
+            AnyPDFData::Real(real) => Ok(*real),
+            AnyPDFData::Integer(int) => Ok(*int as f64),
+            other => Err(Self::mismatch(key, "a number", other)),
+        }
+    }
+    pub fn get_array(&self, key: &str) -> Result<&[AnyPDFData], PdfError> {
+        match self.require(key)? {
+            AnyPDFData::Array(array) => Ok(array),
+            other => Err(Self::mismatch(key, "an array", other)),
+        }
+    }
+    /// Reads an indirect reference as its object number and generation.
+    pub fn get_ref(&self, key: &str) -> Result<(i64, i64), PdfError> {
+        match self.require(key)? {
+            AnyPDFData::ObjRef(number, gen) => Ok((*number, *gen)),
+            other => Err(Self::mismatch(key, "a reference", other)),
+        }
+    }
+    pub fn get_dict(&self, key: &str) -> Result<&Dictionary, PdfError> {
+        match self.require(key)? {
+            AnyPDFData::Dictionary(dict) => Ok(dict),
+            other => Err(Self::mismatch(key, "a dictionary", other)),
+        }
+    }
+}
+
+impl PartialEq for Dictionary {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries == other.entries
+    }
+}
+
+impl fmt::Debug for Dictionary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Into<Name>> From<Vec<(K, AnyPDFData)>> for Dictionary {
+    fn from(entries: Vec<(K, AnyPDFData)>) -> Self {
+        entries.into_iter().collect()
+    }
+}
+
+impl<K: Into<Name>> FromIterator<(K, AnyPDFData)> for Dictionary {
+    fn from_iter<I: IntoIterator<Item = (K, AnyPDFData)>>(iter: I) -> Self {
+        let mut dict = Dictionary::new();
+        for (key, value) in iter {
+            dict.insert(key, value);
+        }
+        dict
+    }
+}
+
+impl IntoIterator for Dictionary {
+    type Item = (Name, AnyPDFData);
+    type IntoIter = std::vec::IntoIter<(Name, AnyPDFData)>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
 impl Clone for Object {
     fn clone(&self) -> Self {
         Object {
@@ -53,6 +420,29 @@ impl Clone for PDF {
         PDF {
             version: self.version.clone(),
             objects: self.objects.clone(),
+            xref: self.xref.clone(),
+            trailer: self.trailer.clone(),
+            revisions: self.revisions.clone(),
+            recovered: self.recovered.clone(),
         }
     }
 }
+
+impl PDF {
+    pub fn trailer_get(&self, key: &str) -> Option<&AnyPDFData> {
+        self.trailer.get(key)
+    }
+    /// The /Root entry of the trailer, a reference to the document catalog.
+    pub fn root(&self) -> Option<&AnyPDFData> {
+        self.trailer_get("Root")
+    }
+    pub fn info(&self) -> Option<&AnyPDFData> {
+        self.trailer_get("Info")
+    }
+    pub fn size(&self) -> Option<i64> {
+        self.trailer.get_int("Size").ok()
+    }
+    pub fn id(&self) -> Option<&AnyPDFData> {
+        self.trailer_get("ID")
+    }
+}